@@ -0,0 +1,35 @@
+//! Source IP filtering for the HTTP server.
+
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use crate::config::ServerConfig;
+
+/// Decides which peers may connect, based on their IP address.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            allow: config.allow_ips.clone(),
+            deny: config.deny_ips.clone(),
+        }
+    }
+
+    /// Whether `peer` may connect: it must not be in a denied network and, if any networks are
+    /// allowed, must be in one of them.
+    pub fn is_allowed(&self, peer: IpAddr) -> bool {
+        // IPv4 peers on dual-stack sockets show up as IPv4-mapped IPv6 addresses.
+        let peer = peer.to_canonical();
+        if self.deny.iter().any(|el| el.contains(&peer)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|el| el.contains(&peer))
+    }
+}
@@ -6,22 +6,29 @@ use std::process::exit;
 use anyhow::Result;
 
 /// Args passed into the application.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CliArgs {
-    /// Optional listen address. By default, listens on `127.0.0.1`
-    pub listen_address: String,
-    /// Optional listen port, by default, listens on `9774`.
-    pub listen_port: u16,
+    /// Addresses to listen on, see [`crate::config::ListenAddr::parse`]. Overrides
+    /// `server.listen` from the config if not empty.
+    pub listen_addresses: Vec<String>,
+    /// Optional listen port for addresses without one. Overrides `server.port` from the config.
+    pub listen_port: Option<u16>,
     /// Path to config.
     pub config_path: PathBuf,
+    /// Reload the config when the file changes, in addition to on `SIGHUP`.
+    pub watch_config: bool,
+    /// Only validate the config, print the effective configuration and exit.
+    pub check_config: bool,
 }
 
 impl Default for CliArgs {
     fn default() -> Self {
         Self {
-            listen_address: "127.0.0.1".to_owned(),
-            listen_port: 9774,
+            listen_addresses: Vec::new(),
+            listen_port: None,
             config_path: PathBuf::from("/etc/litemon/config.kdl"),
+            watch_config: false,
+            check_config: false,
         }
     }
 }
@@ -53,10 +60,18 @@ impl CliArgs {
                     exit(0);
                 }
                 Short('n') | Long("listen") => {
-                    ret.listen_address = parser.value()?.to_string_lossy().to_string();
+                    let addr = parser.value()?.to_string_lossy().to_string();
+                    ret.listen_addresses.push(addr);
                 }
                 Short('P') | Long("port") => {
-                    ret.listen_port = parser.value()?.parse()?;
+                    ret.listen_port = Some(parser.value()?.parse()?);
+                }
+                Short('w') | Long("watch-config") => {
+                    ret.watch_config = true;
+                }
+                Long("check-config") => {
+                    ret.check_config = true;
+                    ret.config_path = PathBuf::from(parser.value()?);
                 }
                 Value(path) => {
                     ret.config_path = PathBuf::from(path);
@@ -75,8 +90,18 @@ impl CliArgs {
         println!("Usage: litemon [OPTIONS] [PATH-TO-CONFIG]");
         println!();
         println!("Options:");
-        println!("-n, --listen          IP address to listen. Default: 127.0.0.1");
-        println!("-P, --port            Port to listen. Default: 9774");
+        println!(
+            "-n, --listen          IP, IP:PORT or unix:PATH to listen. Repeatable. Overrides config. Default: 127.0.0.1"
+        );
+        println!(
+            "-P, --port            Port to listen for addresses without one. Overrides config. Default: 9774"
+        );
+        println!(
+            "-w, --watch-config    Reload config when the file changes (always reloaded on SIGHUP)"
+        );
+        println!(
+            "    --check-config    Validate config at PATH, print the effective config and exit"
+        );
         println!("-V, --version         Print version info and exit");
         println!("-h, --help            Print help and exit");
     }
@@ -86,4 +111,3 @@ impl CliArgs {
         println!("litemon - v{}", env!("CARGO_PKG_VERSION"));
     }
 }
-
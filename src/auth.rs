@@ -0,0 +1,191 @@
+//! Authentication of HTTP requests.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use blake2::{Blake2s256, Digest};
+use hashbrown::HashSet;
+use http::{HeaderMap, HeaderValue, header};
+use smol::lock::RwLock;
+
+use crate::config::AuthConfig;
+
+/// bcrypt hash with the default cost, verified against for unknown usernames so they take as long
+/// to reject as wrong passwords.
+const DUMMY_HASH: &str = "$2b$12$QPMNxhBxRyNnWP7hhLv1ceaGOwI4308ICiu9dmyYynpnGHPIVeikK";
+
+/// Username and digest of the password of basic auth credentials.
+type Credentials = (String, [u8; 32]);
+
+/// Checks the credentials of requests against the configured users and tokens.
+#[derive(Debug, Clone)]
+pub struct Auth {
+    config: AuthConfig,
+    /// Accepted bearer tokens, read from [`AuthConfig::bearer_token_file`].
+    tokens: Arc<RwLock<Vec<String>>>,
+    /// Username and password digest of basic auth credentials that were verified already, so
+    /// only the first request of a scraper pays for the slow password hash.
+    verified: Arc<RwLock<HashSet<Credentials>>>,
+}
+
+impl Auth {
+    /// Read the bearer tokens referenced by `config`.
+    pub async fn new(config: &AuthConfig) -> Result<Self> {
+        let tokens = match &config.bearer_token_file {
+            Some(path) => read_tokens(path).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            tokens: Arc::new(RwLock::new(tokens)),
+            verified: Arc::default(),
+        })
+    }
+
+    /// Re-read the bearer tokens from disk. Keeps the current tokens if that fails.
+    pub async fn reload(&self) -> Result<()> {
+        if let Some(path) = &self.config.bearer_token_file {
+            let tokens = read_tokens(path).await?;
+            *self.tokens.write().await = tokens;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the `Authorization` header in `headers` carries valid credentials.
+    pub async fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(authorization) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|el| el.to_str().ok())
+        else {
+            return false;
+        };
+        let Some((scheme, credentials)) = authorization.split_once(' ') else {
+            return false;
+        };
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            let tokens = self.tokens.read().await;
+            // Compare against every token, so the time taken doesn't reveal which one matched.
+            tokens
+                .iter()
+                .fold(false, |acc, el| constant_time_eq(el, credentials) | acc)
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let Some((username, password)) = decode_basic(credentials) else {
+                return false;
+            };
+            self.verify_basic(username, password).await
+        } else {
+            false
+        }
+    }
+
+    /// Whether `password` is the one of the basic auth user `username`.
+    async fn verify_basic(&self, username: String, password: String) -> bool {
+        let hash = self
+            .config
+            .basic_auth_users
+            .iter()
+            .find(|(el, _)| *el == username)
+            .map(|(_, hash)| hash.clone());
+        let key = (username, Blake2s256::digest(&password).into());
+        if self.verified.read().await.contains(&key) {
+            return true;
+        }
+
+        // Unknown users are checked against a dummy hash as well, so the time taken doesn't reveal
+        // which usernames exist. Hashing is deliberately slow, so keep it off the executor.
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| DUMMY_HASH.to_owned());
+        let valid = smol::unblock(move || verify_password(&password, &hash)).await && known;
+        if valid {
+            self.verified.write().await.insert(key);
+        }
+
+        valid
+    }
+
+    /// Values of the `WWW-Authenticate` header for the enabled methods.
+    pub fn challenges(&self) -> Vec<HeaderValue> {
+        let mut ret = Vec::new();
+        if !self.config.basic_auth_users.is_empty() {
+            ret.push(HeaderValue::from_static(
+                r#"Basic realm="litemon", charset="UTF-8""#,
+            ));
+        }
+        if self.config.bearer_token_file.is_some() {
+            ret.push(HeaderValue::from_static(r#"Bearer realm="litemon""#));
+        }
+
+        ret
+    }
+}
+
+/// Check that `hash` is a bcrypt or argon2 password hash.
+pub(crate) fn validate_hash(hash: &str) -> Result<()> {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).map_err(|err| anyhow::anyhow!("{err}"))?;
+    } else {
+        hash.parse::<bcrypt::HashParts>()
+            .context("expected a bcrypt or argon2 hash")?;
+    }
+
+    Ok(())
+}
+
+/// Whether `password` matches the bcrypt or argon2 `hash`.
+fn verify_password(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    } else {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+/// Decode the credentials of a basic auth header into username and password.
+fn decode_basic(credentials: &str) -> Option<(String, String)> {
+    let decoded = BASE64_STANDARD.decode(credentials).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_owned(), password.to_owned()))
+}
+
+/// Compare `a` and `b` in time independent of where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Read one token per line from `path`, skipping empty lines and `#` comments.
+async fn read_tokens(path: &Path) -> Result<Vec<String>> {
+    let content = smol::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("reading bearer tokens {}", path.display()))?;
+    let tokens = content
+        .lines()
+        .map(str::trim)
+        .filter(|el| !el.is_empty() && !el.starts_with('#'))
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(
+        !tokens.is_empty(),
+        "no bearer tokens found in {}",
+        path.display()
+    );
+
+    Ok(tokens)
+}
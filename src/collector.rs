@@ -1,30 +1,75 @@
 //! Collector for metrics.
 #![allow(clippy::new_without_default)]
 
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
-use futures_concurrency::future::Join;
+use anyhow::Result;
 use hashbrown::HashMap;
-use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::{encode_eof, encode_registry};
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 use smol::lock::RwLock;
+use smol::stream::StreamExt;
 
-use crate::config::UserConfig;
+use crate::config::{MetricsConfig, UserConfig};
+use crate::metrics::Metric;
 use crate::metrics::collector::{
-    CpuStatsCollector, DiskStatsCollector, FilesystemStatsCollector, MemoryStatsCollector, NetworkStatsCollector, NodeInfoCollector, NodeUptimeCollector, PressureCollector, SystemdUnitStateCollector
+    CpuUsageCollector, DiskStatsCollector, FilesystemStatsCollector, LoadAvgCollector,
+    MemoryStatsCollector, NetworkStatsCollector, NodeInfoCollector, NodeUptimeCollector,
+    PressureCollector, SystemdUnitStateCollector, VmStatCollector,
 };
-use crate::metrics::Metric;
+use crate::protobuf::{self, MetricFamily};
 
 #[derive(Debug)]
 struct CollectorInner {
+    /// Registry of the scrape stats. Each metric has its own registry, so the metrics can be
+    /// encoded selectively.
+    registry: Registry,
+    metrics: Vec<Arc<MetricEntry>>,
+    stats: ScrapeStats,
+    /// Background tasks sampling the metrics. Dropping them stops the sampling.
+    tasks: Vec<smol::Task<()>>,
+}
+
+/// A created metric together with its collection settings.
+#[derive(Debug)]
+struct MetricEntry {
+    metric: Box<dyn Metric>,
     registry: Registry,
-    metrics: Vec<Box<dyn Metric>>,
+    /// Time after which a collection is aborted.
+    timeout: Duration,
+    /// Time between two collections.
+    interval: Duration,
+    /// Whether the metric has been collected at least once, successfully or not.
+    collected: AtomicBool,
+    /// When the outcome of the last collection or skipped tick was recorded, or the metric was
+    /// created.
+    last_collected: Mutex<Instant>,
+}
+
+/// Outcome of the last collection of each collector.
+#[derive(Debug, Default, Clone)]
+struct ScrapeStats {
+    success: Family<ScrapeLabels, Gauge>,
+    timed_out: Family<ScrapeLabels, Gauge>,
+    duration: Family<ScrapeLabels, Gauge<f64, AtomicU64>>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ScrapeLabels {
+    /// Name of the collector, see [`Metric::name`].
+    collector: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct Collector {
     inner: Arc<RwLock<CollectorInner>>,
+    /// Set once all collectors have been started and collected at least once.
+    ready: Arc<AtomicBool>,
 }
 
 impl CollectorInner {
@@ -32,114 +77,472 @@ impl CollectorInner {
         Self {
             registry: <Registry>::default(),
             metrics: Vec::new(),
+            stats: ScrapeStats::default(),
+            tasks: Vec::new(),
         }
     }
-}
 
-impl Collector {
-    pub fn new() -> Self {
-        let inner = CollectorInner::new();
-        Self {
-            inner: Arc::new(RwLock::new(inner)),
-        }
+    /// Add a created metric, collected every `interval` and timed out after `timeout`.
+    fn push(&mut self, metric: Box<dyn Metric>, timeout: Duration, interval: Duration) {
+        let mut registry = <Registry>::default();
+        metric.register(&mut registry);
+        self.metrics.push(Arc::new(MetricEntry {
+            metric,
+            registry,
+            timeout,
+            interval,
+            collected: AtomicBool::new(false),
+            last_collected: Mutex::new(Instant::now()),
+        }));
+    }
+
+    /// Add a created metric with optional `timeout` and `interval`, falling back to the defaults
+    /// in `metrics`.
+    fn push_with_defaults(
+        &mut self,
+        metric: Box<dyn Metric>,
+        metrics: &MetricsConfig,
+        timeout: Option<Duration>,
+        interval: Option<Duration>,
+    ) {
+        self.push(
+            metric,
+            timeout.unwrap_or(metrics.timeout),
+            interval.unwrap_or(metrics.interval),
+        );
     }
 
     /// Create collectors from configuration.
-    pub async fn create_from_config(&self, config: &UserConfig) -> Result<()> {
-        let mut inner = self.inner.write().await;
+    async fn from_config(config: &UserConfig) -> Result<Self> {
+        let mut inner = Self::new();
 
         let metrics = &config.metrics;
         {
             let collector = Box::new(NodeInfoCollector::new()?);
-            inner.metrics.push(collector);
+            inner.push(collector, metrics.timeout, metrics.interval);
         }
         {
             let collector = Box::new(NodeUptimeCollector::default());
-            inner.metrics.push(collector);
-        }
-
-        if metrics.cpu_seconds.enabled || metrics.loadavg.enabled {
-            // TODO: Fix disabling of CPU seconds.
-            let collector = Box::new(
-                CpuStatsCollector::new(if metrics.loadavg.enabled {
-                    HashMap::from([("load_avg_enabled".to_owned(), "true".to_owned())])
-                } else {
-                    Default::default()
-                })
-                .await?,
-            );
-            inner.metrics.push(collector);
+            inner.push(collector, metrics.timeout, metrics.interval);
+        }
+
+        if metrics.loadavg.enabled {
+            let collector = Box::new(LoadAvgCollector::default());
+            let schedule = &metrics.loadavg;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
+        }
+
+        if metrics.cpu_seconds.enabled {
+            let period = metrics.cpu_seconds.period.as_millis().to_string();
+            let options = HashMap::from([("period_ms".to_owned(), period)]);
+            let collector = Box::new(CpuUsageCollector::new(&options)?);
+            let schedule = &metrics.cpu_seconds;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.memory_used.enabled {
-            let collector = Box::new(MemoryStatsCollector::default());
-            inner.metrics.push(collector);
+            let fields = metrics.memory_used.fields.join(",");
+            let options = HashMap::from([("fields".to_owned(), fields)]);
+            let collector = Box::new(MemoryStatsCollector::new(&options)?);
+            let schedule = &metrics.memory_used;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.systemd_unit_state.enabled {
             let units = metrics.systemd_unit_state.units.join(",");
             let options = HashMap::from([("units".to_owned(), units)]);
             let collector = Box::new(SystemdUnitStateCollector::new(&options).await?);
-            inner.metrics.push(collector);
+            let schedule = &metrics.systemd_unit_state;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.network_throughput.enabled {
             let interfaces = metrics.network_throughput.interfaces.join(",");
             let options = HashMap::from([("interfaces".to_owned(), interfaces)]);
             let collector = Box::new(NetworkStatsCollector::new(&options)?);
-            inner.metrics.push(collector);
+            let schedule = &metrics.network_throughput;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.disk_usage.enabled {
             let mountpoints = metrics.disk_usage.mountpoints.join(",");
             let options = HashMap::from([("mountpoints".to_owned(), mountpoints)]);
             let collector = Box::new(FilesystemStatsCollector::new(&options)?);
-            inner.metrics.push(collector);
+            let schedule = &metrics.disk_usage;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.pressure.enabled {
             let collector = Box::new(PressureCollector::default());
-            inner.metrics.push(collector);
+            let schedule = &metrics.pressure;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
         }
 
         if metrics.disk_stats.enabled {
             let mountpoints = metrics.disk_stats.mountpoints.join(",");
             let options = HashMap::from([("mountpoints".to_owned(), mountpoints)]);
             let collector = Box::new(DiskStatsCollector::new(&options)?);
-            inner.metrics.push(collector);
+            let schedule = &metrics.disk_stats;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
+        }
+
+        if metrics.vmstat.enabled {
+            let fields = metrics.vmstat.fields.join(",");
+            let options = HashMap::from([("fields".to_owned(), fields)]);
+            let collector = Box::new(VmStatCollector::new(&options)?);
+            let schedule = &metrics.vmstat;
+            inner.push_with_defaults(collector, metrics, schedule.timeout, schedule.interval);
+        }
+
+        Ok(inner)
+    }
+
+    /// Register the scrape stats. The metrics are registered into their own registry when they
+    /// are added.
+    fn register(&mut self) {
+        self.stats.register(&mut self.registry);
+    }
+
+    /// Call `f` with the registries of the collectors named in `selected`, or of all collectors
+    /// if it is empty, and then with the registry of their scrape stats.
+    fn for_each_registry(
+        &self,
+        selected: &[String],
+        mut f: impl FnMut(&Registry) -> Result<()>,
+    ) -> Result<()> {
+        let is_selected = |name: &str| selected.is_empty() || selected.iter().any(|el| el == name);
+        for name in selected {
+            anyhow::ensure!(
+                self.metrics.iter().any(|el| el.metric.name() == name),
+                "unknown collector: {name}"
+            );
+        }
+
+        for entry in self
+            .metrics
+            .iter()
+            .filter(|el| is_selected(el.metric.name()))
+        {
+            f(&entry.registry)?;
+        }
+        if selected.is_empty() {
+            f(&self.registry)
+        } else {
+            let mut registry = <Registry>::default();
+            self.stats.subset(selected).register(&mut registry);
+            f(&registry)
+        }
+    }
+
+    /// Encode the metrics of the collectors named in `selected`, or of all collectors if it is
+    /// empty, in OpenMetrics format.
+    fn encode(&self, selected: &[String]) -> Result<String> {
+        let mut buf = String::with_capacity(2048);
+        self.for_each_registry(selected, |registry| {
+            Ok(encode_registry(&mut buf, registry)?)
+        })?;
+        encode_eof(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    /// Metric families of the collectors named in `selected`, or of all collectors if it is
+    /// empty.
+    fn families(&self, selected: &[String]) -> Result<Vec<MetricFamily>> {
+        let mut ret = Vec::new();
+        self.for_each_registry(selected, |registry| {
+            ret.extend(protobuf::from_registry(registry)?);
+            Ok(())
+        })?;
+
+        Ok(ret)
+    }
+
+    /// Start sampling all metrics in the background, each on its own interval.
+    fn start(&mut self) {
+        self.tasks = self
+            .metrics
+            .iter()
+            .map(|entry| {
+                let entry = Arc::clone(entry);
+                let stats = self.stats.clone();
+                smol::spawn(async move { entry.run(&stats).await })
+            })
+            .collect();
+    }
+
+    /// Stop sampling and release the resources held by the metrics, see [`Collector::shutdown`].
+    async fn shutdown(&mut self) {
+        // Cancels collections in progress as well. Waits for tasks currently running on another
+        // thread, so no collection overlaps with shutting down the metrics.
+        for task in self.tasks.drain(..) {
+            task.cancel().await;
+        }
+        for entry in &self.metrics {
+            if let Err(err) = entry.metric.shutdown().await {
+                tracing::warn!(
+                    collector = entry.metric.name(),
+                    "shutting down failed: {err:#}"
+                );
+            }
+        }
+    }
+}
+
+impl ScrapeStats {
+    fn register(&self, registry: &mut Registry) {
+        registry.register(
+            "litemon_scrape_collector_success",
+            "Whether the last collection of the collector succeeded (1) or failed (0)",
+            self.success.clone(),
+        );
+        registry.register(
+            "litemon_scrape_collector_timed_out",
+            "Whether the last collection of the collector exceeded its timeout (1) or not (0)",
+            self.timed_out.clone(),
+        );
+        registry.register(
+            "litemon_scrape_collector_duration_seconds",
+            "Duration of the last collection of the collector in seconds",
+            self.duration.clone(),
+        );
+    }
+
+    /// Copy of the stats of the collectors named in `names`.
+    fn subset(&self, names: &[String]) -> Self {
+        let ret = Self::default();
+        for name in names {
+            let labels = ScrapeLabels {
+                collector: name.clone(),
+            };
+            if let Some(success) = self.success.get(&labels) {
+                ret.success.get_or_create(&labels).set(success.get());
+            }
+            if let Some(timed_out) = self.timed_out.get(&labels) {
+                ret.timed_out.get_or_create(&labels).set(timed_out.get());
+            }
+            if let Some(duration) = self.duration.get(&labels) {
+                ret.duration.get_or_create(&labels).set(duration.get());
+            }
+        }
+
+        ret
+    }
+}
+
+impl MetricEntry {
+    /// Collect the metric every [`Self::interval`], forever.
+    ///
+    /// A collection exceeding [`Self::timeout`] is reported as timed out, but not dropped: it
+    /// may be stuck in a blocking call that cannot be cancelled, e.g., `statvfs` on a stale NFS
+    /// mount, and dropping it would leak another blocking thread on every tick. Instead, no new
+    /// collection is started until it finishes, and every tick skipped meanwhile is reported as
+    /// timed out as well.
+    async fn run(&self, stats: &ScrapeStats) {
+        let mut ticker = smol::Timer::interval(self.interval);
+        loop {
+            let start = Instant::now();
+            let mut collection = self.metric.collect();
+            let res = smol::future::or(async { Some((&mut collection).await) }, async {
+                smol::Timer::after(self.timeout).await;
+                None
+            })
+            .await;
+            let timed_out = res.is_none();
+            self.record(stats, res, start.elapsed());
+
+            if timed_out {
+                loop {
+                    let late = smol::future::or(async { Some((&mut collection).await) }, async {
+                        ticker.next().await;
+                        None
+                    })
+                    .await;
+                    if let Some(outcome) = late {
+                        tracing::debug!(
+                            collector = self.metric.name(),
+                            "timed out collection finished after {}ms: {outcome:?}",
+                            start.elapsed().as_millis()
+                        );
+                        break;
+                    }
+                    self.record(stats, None, start.elapsed());
+                }
+            }
+
+            if ticker.next().await.is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Record the outcome of a collection in `stats`, `None` meaning that it timed out.
+    ///
+    /// A failing or timed-out collection is logged and reported via
+    /// `litemon_scrape_collector_success`, but does not affect any other collector.
+    fn record(&self, stats: &ScrapeStats, res: Option<Result<()>>, elapsed: Duration) {
+        let name = self.metric.name();
+        let labels = ScrapeLabels {
+            collector: name.to_owned(),
+        };
+        let (success, timed_out) = match res {
+            Some(Ok(())) => (1, 0),
+            Some(Err(err)) => {
+                tracing::warn!(collector = name, "collecting metrics failed: {err:#}");
+                (0, 0)
+            }
+            None => {
+                tracing::warn!(
+                    collector = name,
+                    "collecting metrics timed out after {}ms",
+                    elapsed.as_millis()
+                );
+                (0, 1)
+            }
+        };
+        stats.success.get_or_create(&labels).set(success);
+        stats.timed_out.get_or_create(&labels).set(timed_out);
+        stats
+            .duration
+            .get_or_create(&labels)
+            .set(elapsed.as_secs_f64());
+        self.collected.store(true, Ordering::Relaxed);
+        *self
+            .last_collected
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    /// Whether the last outcome was recorded recently enough for the sampling task to be alive,
+    /// i.e., within one interval and timeout.
+    fn is_alive(&self) -> bool {
+        let last_collected = *self
+            .last_collected
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        last_collected.elapsed() <= self.interval + self.timeout
+    }
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        let inner = CollectorInner::new();
+        Self {
+            inner: Arc::new(RwLock::new(inner)),
+            ready: Arc::new(AtomicBool::new(false)),
         }
+    }
+
+    /// Create collectors from configuration.
+    pub async fn create_from_config(&self, config: &UserConfig) -> Result<()> {
+        let created = CollectorInner::from_config(config).await?;
+        *self.inner.write().await = created;
 
         Ok(())
     }
 
+    /// Add `metric`, collected every `interval` and timed out after `timeout`, next to the
+    /// collectors created from configuration. Must be called before [`Self::register`].
+    pub async fn push(&self, metric: Box<dyn Metric>, timeout: Duration, interval: Duration) {
+        self.inner.write().await.push(metric, timeout, interval);
+    }
+
     /// Register all previously created metrics.
     pub async fn register(&self) -> Result<()> {
-        let mut writer = self.inner.write().await;
-        let CollectorInner { metrics, registry } = &mut *writer;
-        for metric in metrics {
-            metric.register(registry);
-        }
+        self.inner.write().await.register();
+
+        Ok(())
+    }
+
+    /// Start sampling all registered metrics in the background, each on its own interval.
+    ///
+    /// Every metric is collected once right away, so the registry is populated by the time the
+    /// first scrape arrives.
+    pub async fn start(&self) {
+        self.inner.write().await.start();
+    }
+
+    /// Rebuild all collectors from `config` and atomically swap them in.
+    ///
+    /// The new collectors are created, registered into a fresh registry and started before
+    /// replacing the current ones, which are stopped afterwards. If creating the new collectors
+    /// fails, the current ones keep running.
+    pub async fn reload(&self, config: &UserConfig) -> Result<()> {
+        let mut created = CollectorInner::from_config(config).await?;
+        created.register();
+        created.start();
+        let mut replaced = std::mem::replace(&mut *self.inner.write().await, created);
+        replaced.shutdown().await;
 
         Ok(())
     }
 
-    /// Collect all metrics and return the serialized response in OpenMetrics format as a String.
-    pub async fn collect_and_encode(&self) -> Result<String> {
+    /// Stop sampling and release the resources held by the metrics, e.g., D-Bus connections.
+    ///
+    /// Called on exit, and for the replaced collectors on reload. Failures are logged, as there
+    /// is nothing left to do about them.
+    pub async fn shutdown(&self) {
+        self.inner.write().await.shutdown().await;
+    }
+
+    /// Return the most recently sampled metrics serialized in OpenMetrics format as a String.
+    ///
+    /// Only the collectors named in `selected` (see [`Metric::name`]) are included, or all of
+    /// them if it is empty. Fails if `selected` names a collector that is not enabled.
+    pub async fn encode(&self, selected: &[String]) -> Result<String> {
+        self.inner.read().await.encode(selected)
+    }
+
+    /// Return the most recently sampled metrics as metric families, e.g., to serve them in the
+    /// Prometheus protobuf format.
+    ///
+    /// Only the collectors named in `selected` are included, like in [`Self::encode`].
+    pub async fn families(&self, selected: &[String]) -> Result<Vec<MetricFamily>> {
+        self.inner.read().await.families(selected)
+    }
+
+    /// Names of all enabled collectors, see [`Metric::name`].
+    pub async fn names(&self) -> Vec<&'static str> {
         let inner = self.inner.read().await;
-        let futs: Vec<_> = inner
-            .metrics
-            .iter()
-            .map(|metric| metric.collect())
-            .collect();
-        let results = futs.join().await;
-        for res in results {
-            res.inspect_err(|err| eprintln!("{err}"))
-                .context("collecting metrics")?;
+        inner.metrics.iter().map(|el| el.metric.name()).collect()
+    }
+
+    /// Whether all collectors have been initialized and collected at least once.
+    ///
+    /// Stays ready across reloads, as the new collectors are only swapped in once they have been
+    /// created successfully.
+    pub async fn is_ready(&self) -> bool {
+        if self.ready.load(Ordering::Relaxed) {
+            return true;
         }
 
-        let mut buf = String::with_capacity(2048);
-        encode(&mut buf, &inner.registry)?;
+        let inner = self.inner.read().await;
+        let ready = !inner.tasks.is_empty()
+            && inner
+                .metrics
+                .iter()
+                .all(|el| el.collected.load(Ordering::Relaxed));
+        if ready {
+            self.ready.store(true, Ordering::Relaxed);
+        }
 
-        Ok(buf)
+        ready
+    }
+
+    /// Whether metrics are still being sampled, i.e., every collector finished a collection
+    /// within its interval and timeout.
+    pub async fn is_alive(&self) -> bool {
+        let inner = self.inner.read().await;
+        !inner.tasks.is_empty() && inner.metrics.iter().all(|el| el.is_alive())
+    }
+
+    /// The names in `names` that do not belong to an enabled collector.
+    pub async fn unknown_collectors<'a>(&self, names: &'a [String]) -> Vec<&'a str> {
+        let inner = self.inner.read().await;
+        names
+            .iter()
+            .filter(|name| !inner.metrics.iter().any(|el| el.metric.name() == *name))
+            .map(String::as_str)
+            .collect()
     }
 }
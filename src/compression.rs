@@ -0,0 +1,106 @@
+//! Compression of HTTP responses.
+
+use std::io::Write;
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use http::{HeaderMap, HeaderValue, header};
+
+/// Content coding of a response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    /// Supported encodings, in order of preference if the client accepts several equally.
+    const SUPPORTED: [Self; 3] = [Self::Zstd, Self::Gzip, Self::Identity];
+
+    /// Pick the encoding for the response based on the `Accept-Encoding` header in `headers`.
+    ///
+    /// Returns `None` if the client accepts none of the supported encodings, not even
+    /// `identity`.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let accepted = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|el| el.to_str().ok())
+            .flat_map(|el| el.split(','))
+            .filter_map(parse_coding)
+            .collect::<Vec<_>>();
+        if accepted.is_empty() {
+            return Some(Self::Identity);
+        }
+
+        let quality = |encoding: Self| {
+            let name = encoding.name();
+            let explicit = accepted
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(name));
+            let wildcard = accepted.iter().find(|(coding, _)| *coding == "*");
+            match (explicit, wildcard) {
+                (Some((_, q)), _) | (None, Some((_, q))) => *q,
+                // `identity` is acceptable unless excluded explicitly or via `*;q=0`, but only
+                // as a fallback if no other coding is accepted.
+                (None, None) if encoding == Self::Identity => f32::MIN_POSITIVE,
+                (None, None) => 0.0,
+            }
+        };
+
+        let mut best: Option<(Self, f32)> = None;
+        for encoding in Self::SUPPORTED {
+            let q = quality(encoding);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((encoding, q));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Name of the encoding as used in `Content-Encoding`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+            Self::Identity => "identity",
+        }
+    }
+
+    /// Value of the `Content-Encoding` header, or `None` for [`Self::Identity`].
+    pub const fn header_value(self) -> Option<HeaderValue> {
+        match self {
+            Self::Identity => None,
+            _ => Some(HeaderValue::from_static(self.name())),
+        }
+    }
+
+    /// Compress `body` with this encoding.
+    pub fn encode(self, body: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::Zstd => Ok(zstd::encode_all(
+                body.as_slice(),
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&body)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Identity => Ok(body),
+        }
+    }
+}
+
+/// Parse one element of `Accept-Encoding`, e.g., `gzip;q=0.8`, into coding and quality.
+fn parse_coding(element: &str) -> Option<(&str, f32)> {
+    let mut parts = element.split(';').map(str::trim);
+    let coding = parts.next().filter(|el| !el.is_empty())?;
+    let q = parts
+        .find_map(|el| el.strip_prefix("q=").or_else(|| el.strip_prefix("Q=")))
+        .map_or(Some(1.0), |el| el.parse().ok())?;
+
+    Some((coding, q))
+}
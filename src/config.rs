@@ -1,10 +1,18 @@
 //! LiteMon Configuration.
 
-use std::path::Path;
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
 
 use anyhow::Context;
-use kdl::{KdlDocument, KdlNode};
+use ipnet::IpNet;
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+
+use crate::args::CliArgs;
+use crate::metrics::memory::MeminfoField;
+use crate::metrics::vmstat;
 
 /// Describes the user configuration.
 ///
@@ -14,11 +22,262 @@ use kdl::{KdlDocument, KdlNode};
 pub struct UserConfig {
     /// Everything related to metrics.
     pub metrics: MetricsConfig,
+    /// The HTTP server serving the metrics.
+    pub server: ServerConfig,
+    /// Logging.
+    pub log: LogConfig,
+}
+
+/// Describes the configuration of the HTTP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Addresses to listen on, see [`ListenAddr::parse`]. Can be overridden with `--listen`.
+    pub listen: Vec<String>,
+    /// Port to listen on for addresses without one. Can be overridden with `--port`.
+    pub port: u16,
+    /// Serve over TLS instead of plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// Require authentication for the metrics.
+    pub auth: Option<AuthConfig>,
+    /// Only accept connections from these networks. Accepts all peers if empty.
+    pub allow_ips: Vec<IpNet>,
+    /// Never accept connections from these networks. Takes precedence over `allow_ips`.
+    pub deny_ips: Vec<IpNet>,
+    /// Permissions of the Unix domain sockets, if listening on any.
+    pub socket: SocketConfig,
+    /// Time in-flight requests get to finish on `SIGTERM` or `SIGINT` before exiting anyway.
+    pub grace_period: Duration,
+}
+
+/// An address to listen on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    /// Path of a Unix domain socket.
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// Parse `unix:<path>`, `<ip>:<port>`, `[<ipv6>]:<port>`, or an IP address, which uses
+    /// `default_port`.
+    pub fn parse(value: &str, default_port: u16) -> anyhow::Result<Self> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            anyhow::ensure!(!path.is_empty(), "missing socket path in `{value}`");
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        value
+            .parse::<SocketAddr>()
+            .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+            .map(Self::Tcp)
+            .with_context(|| {
+                format!("invalid listen address `{value}`, expected an IP address, `ip:port` or `unix:PATH`")
+            })
+    }
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Describes the permissions of the Unix domain socket.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketConfig {
+    /// File mode, e.g., `0o660`. Uses the umask if unset.
+    pub mode: Option<u32>,
+    /// Name or ID of the user owning the socket.
+    pub owner: Option<String>,
+    /// Name or ID of the group owning the socket.
+    pub group: Option<String>,
+}
+
+/// Describes how clients authenticate. A request is accepted if it passes any of the methods.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// Usernames with their bcrypt or argon2 password hashes for HTTP basic auth.
+    pub basic_auth_users: Vec<(String, String)>,
+    /// Path to a file with one accepted bearer token per line.
+    pub bearer_token_file: Option<PathBuf>,
+}
+
+/// Describes the TLS configuration of the HTTP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// Path to the PEM-encoded certificate chain.
+    pub cert: PathBuf,
+    /// Path to the PEM-encoded private key.
+    pub key: PathBuf,
+    /// Path to a PEM-encoded CA bundle. If set, clients must present a certificate signed by one
+    /// of these CAs.
+    pub client_ca: Option<PathBuf>,
+    /// Subject CNs or SANs of client certificates allowed to connect. Allows all clients with a
+    /// valid certificate if empty.
+    pub allowed_names: Vec<String>,
+}
+
+/// Describes the logging configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogConfig {
+    /// Filter directives in `RUST_LOG` syntax (e.g., `info` or `litemon=debug`). If unset, uses
+    /// `RUST_LOG`, or logs litemon at `debug` level. `RUST_LOG` takes precedence over this value.
+    pub level: Option<String>,
+    pub format: LogFormat,
+}
+
+/// Output format of the logs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Logfmt,
+    Json,
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Logfmt => write!(f, "logfmt"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "logfmt" => Ok(Self::Logfmt),
+            "json" => Ok(Self::Json),
+            format => Err(anyhow::anyhow!(
+                "unknown log format `{format}`, expected one of: logfmt, json"
+            )),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// All addresses to listen on, using [`Self::port`] for those without a port.
+    pub fn listen_addrs(&self) -> anyhow::Result<Vec<ListenAddr>> {
+        self.listen
+            .iter()
+            .map(|el| ListenAddr::parse(el, self.port))
+            .collect()
+    }
+
+    fn to_kdl(&self) -> KdlNode {
+        let mut node = KdlNode::new("server");
+        let children = node.ensure_children().nodes_mut();
+        let mut listen = KdlNode::new("listen");
+        for addr in &self.listen {
+            listen.push(addr.as_str());
+        }
+        children.push(listen);
+        children.push(arg_node("port", i128::from(self.port)));
+        children.push(arg_node("grace_period_ms", millis(self.grace_period)));
+        if let Some(tls) = &self.tls {
+            let mut tls_node = KdlNode::new("tls");
+            let tls_children = tls_node.ensure_children().nodes_mut();
+            tls_children.push(arg_node("cert", tls.cert.to_string_lossy().as_ref()));
+            tls_children.push(arg_node("key", tls.key.to_string_lossy().as_ref()));
+            if let Some(client_ca) = &tls.client_ca {
+                tls_children.push(arg_node("client_ca", client_ca.to_string_lossy().as_ref()));
+            }
+            children.push(with_list(tls_node, "allowed_names", &tls.allowed_names));
+        }
+        if let Some(auth) = &self.auth {
+            let mut auth_node = KdlNode::new("auth");
+            let auth_children = auth_node.ensure_children().nodes_mut();
+            if !auth.basic_auth_users.is_empty() {
+                let mut users = KdlNode::new("basic_auth_users");
+                let user_nodes = users.ensure_children().nodes_mut();
+                // The output ends up in terminals and logs, and hashes can be cracked offline.
+                for (username, _) in &auth.basic_auth_users {
+                    user_nodes.push(arg_node(username, REDACTED));
+                }
+                auth_children.push(users);
+            }
+            if let Some(path) = &auth.bearer_token_file {
+                auth_children.push(arg_node(
+                    "bearer_token_file",
+                    path.to_string_lossy().as_ref(),
+                ));
+            }
+            children.push(auth_node);
+        }
+
+        if self.socket.mode.is_some() || self.socket.owner.is_some() || self.socket.group.is_some()
+        {
+            let mut socket_node = KdlNode::new("socket");
+            let socket_children = socket_node.ensure_children().nodes_mut();
+            if let Some(mode) = self.socket.mode {
+                socket_children.push(arg_node("mode", format!("{mode:04o}")));
+            }
+            if let Some(owner) = &self.socket.owner {
+                socket_children.push(arg_node("owner", owner.as_str()));
+            }
+            if let Some(group) = &self.socket.group {
+                socket_children.push(arg_node("group", group.as_str()));
+            }
+            children.push(socket_node);
+        }
+
+        let allow_ips = self
+            .allow_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let deny_ips = self
+            .deny_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        with_list(
+            with_list(node, "allow_ips", &allow_ips),
+            "deny_ips",
+            &deny_ips,
+        )
+    }
+}
+
+impl LogConfig {
+    fn to_kdl(&self) -> KdlNode {
+        let mut node = KdlNode::new("log");
+        let children = node.ensure_children().nodes_mut();
+        if let Some(level) = &self.level {
+            children.push(arg_node("level", level.as_str()));
+        }
+        children.push(arg_node("format", self.format.to_string()));
+        node
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: vec!["127.0.0.1".to_owned()],
+            port: 9774,
+            tls: None,
+            auth: None,
+            allow_ips: Vec::new(),
+            deny_ips: Vec::new(),
+            socket: SocketConfig::default(),
+            grace_period: Duration::from_secs(10),
+        }
+    }
 }
 
 /// Describes the configuration for each supported metric.
 #[derive(Debug)]
 pub struct MetricsConfig {
+    /// Default time after which a collection is aborted. Can be overridden per metric.
+    pub timeout: Duration,
+    /// Default time between two collections. Can be overridden per metric.
+    pub interval: Duration,
     pub cpu_seconds: CpuSecondsConfig,
     pub loadavg: LoadAvgConfig,
     pub memory_used: MemoryUsedConfig,
@@ -27,228 +286,1127 @@ pub struct MetricsConfig {
     pub disk_usage: DiskUsageConfig,
     pub pressure: PressureConfig,
     pub disk_stats: DiskStatConfig,
+    pub vmstat: VmStatConfig,
 }
 
 #[derive(Debug)]
 pub struct CpuSecondsConfig {
     pub enabled: bool,
     pub period: Duration,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct LoadAvgConfig {
     pub enabled: bool,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct MemoryUsedConfig {
     pub enabled: bool,
+    /// Additional `/proc/meminfo` fields to export (e.g., `Dirty`, `HugePages_Total`).
+    pub fields: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct SystemdUnitStateConfig {
     pub enabled: bool,
     pub units: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct NetworkThroughputConfig {
     pub enabled: bool,
     pub interfaces: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct DiskUsageConfig {
     pub enabled: bool,
     pub mountpoints: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct PressureConfig {
     pub enabled: bool,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug)]
 pub struct DiskStatConfig {
     pub enabled: bool,
     pub mountpoints: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct VmStatConfig {
+    pub enabled: bool,
+    /// Fields of `/proc/vmstat` to export. Uses a default set if empty.
+    pub fields: Vec<String>,
+    /// Overrides [`MetricsConfig::timeout`] for this metric.
+    pub timeout: Option<Duration>,
+    /// Overrides [`MetricsConfig::interval`] for this metric.
+    pub interval: Option<Duration>,
 }
 
 impl Default for MetricsConfig {
     fn default() -> Self {
         Self {
+            timeout: Duration::from_secs(5),
+            interval: Duration::from_secs(15),
             cpu_seconds: CpuSecondsConfig {
                 enabled: true,
                 period: Duration::from_millis(200),
+                timeout: None,
+                interval: None,
+            },
+            loadavg: LoadAvgConfig {
+                enabled: true,
+                timeout: None,
+                interval: None,
+            },
+            memory_used: MemoryUsedConfig {
+                enabled: true,
+                fields: vec![],
+                timeout: None,
+                interval: None,
             },
-            loadavg: LoadAvgConfig { enabled: true },
-            memory_used: MemoryUsedConfig { enabled: true },
             systemd_unit_state: SystemdUnitStateConfig {
                 enabled: false,
                 units: vec![],
+                timeout: None,
+                interval: None,
             },
             network_throughput: NetworkThroughputConfig {
                 enabled: false,
                 interfaces: vec![],
+                timeout: None,
+                interval: None,
             },
             disk_usage: DiskUsageConfig {
                 enabled: false,
                 mountpoints: vec![],
+                timeout: None,
+                interval: None,
+            },
+            pressure: PressureConfig {
+                enabled: true,
+                timeout: None,
+                interval: None,
             },
-            pressure: PressureConfig { enabled: true },
             disk_stats: DiskStatConfig {
                 enabled: false,
                 mountpoints: vec![],
+                timeout: None,
+                interval: None,
+            },
+            vmstat: VmStatConfig {
+                enabled: false,
+                fields: vec![],
+                timeout: None,
+                interval: None,
             },
         }
     }
 }
 
+/// A single problem found in the configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Line of the problem, starting at 1.
+    pub line: usize,
+    /// Column of the problem, starting at 1.
+    pub column: usize,
+    /// Description of the problem.
+    pub message: String,
+}
+
+/// All problems found while loading the configuration file at `path`.
+#[derive(Debug)]
+pub struct ConfigErrors {
+    pub path: PathBuf,
+    pub errors: Vec<ConfigError>,
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, err) in self.errors.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path.display(),
+                err.line,
+                err.column,
+                err.message
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Properties accepted by every metric.
+const METRIC_PROPS: &[&str] = &["enabled", "timeout_ms", "interval_ms"];
+
 impl UserConfig {
     /// Load the configuratin from the `path` specified.
-    #[allow(clippy::too_many_lines, clippy::shadow_unrelated)]
+    ///
+    /// Unknown nodes and properties, as well as values of the wrong type, are rejected with a
+    /// [`ConfigErrors`] pointing at their location in the file.
     pub async fn from_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let docstr = smol::fs::read_to_string(path.as_ref())
             .await
             .with_context(|| format!("reading config file: {}", path.as_ref().display()))?;
-        let doc: KdlDocument = docstr.parse().context("parsing config file")?;
-
-        let extract_metrics = |node: &KdlNode| -> MetricsConfig {
-            let mut ret = MetricsConfig::default();
-            if let Some(children) = node.children() {
-                if let Some(node) = children.get("cpu_seconds") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    let period = node
-                        .get("period_ms")
-                        .and_then(|el| el.as_integer())
-                        .unwrap_or(1000_i128) as u64;
-                    ret.cpu_seconds = CpuSecondsConfig {
-                        enabled,
-                        period: Duration::from_millis(period),
-                    }
-                }
 
-                if let Some(node) = children.get("loadavg") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    ret.loadavg = LoadAvgConfig { enabled };
-                }
-
-                if let Some(node) = children.get("memory_used") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    ret.memory_used = MemoryUsedConfig { enabled };
-                }
+        Self::parse(&docstr).map_err(|errors| {
+            ConfigErrors {
+                path: path.as_ref().to_owned(),
+                errors,
+            }
+            .into()
+        })
+    }
 
-                if let Some(node) = children.get("systemd_unit_state") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    let units = node
-                        .children()
-                        .and_then(|el| el.get("units"))
-                        .map(|el| el.entries())
-                        .map(|it| {
-                            it.iter()
-                                .filter_map(|el| el.value().as_string())
-                                .map(ToOwned::to_owned)
-                                .collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    ret.systemd_unit_state = SystemdUnitStateConfig { enabled, units };
-                }
+    /// Parse and validate the configuration in `source`.
+    fn parse(source: &str) -> Result<Self, Vec<ConfigError>> {
+        let mut parser = Parser {
+            source,
+            errors: Vec::new(),
+        };
 
-                if let Some(node) = children.get("network_throughput") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    let interfaces = node
-                        .children()
-                        .and_then(|el| el.get("interfaces"))
-                        .map(|el| el.entries())
-                        .map(|it| {
-                            it.iter()
-                                .filter_map(|el| el.value().as_string())
-                                .map(ToOwned::to_owned)
-                                .collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    ret.network_throughput = NetworkThroughputConfig {
-                        enabled,
-                        interfaces,
+        let doc = match source.parse::<KdlDocument>() {
+            Ok(doc) => doc,
+            Err(err) => {
+                for diag in &err.diagnostics {
+                    let message = match (&diag.message, &diag.help) {
+                        (Some(message), Some(help)) => format!("{message} ({help})"),
+                        (Some(message), None) => message.clone(),
+                        (None, _) => "invalid KDL".to_owned(),
                     };
+                    parser.error(diag.span.offset(), message);
                 }
+                return Err(parser.errors);
+            }
+        };
 
-                if let Some(node) = children.get("disk_usage") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    let mountpoints = node
-                        .children()
-                        .and_then(|el| el.get("mountpoints"))
-                        .map(|el| el.entries())
-                        .map(|it| {
-                            it.iter()
-                                .filter_map(|el| el.value().as_string())
-                                .map(ToOwned::to_owned)
-                                .collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    ret.disk_usage = DiskUsageConfig {
-                        enabled,
-                        mountpoints,
-                    };
-                }
+        parser.check_children(&doc, "the top level", &["metrics", "server", "log"]);
+        let metrics = doc
+            .get("metrics")
+            .map_or_else(MetricsConfig::default, |node| parser.metrics(node));
+        let server = doc
+            .get("server")
+            .map_or_else(ServerConfig::default, |node| parser.server(node));
+        let log = doc
+            .get("log")
+            .map_or_else(LogConfig::default, |node| parser.log(node));
 
-                if let Some(node) = children.get("pressure") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    ret.pressure = PressureConfig { enabled };
-                }
+        if parser.errors.is_empty() {
+            Ok(Self {
+                metrics,
+                server,
+                log,
+            })
+        } else {
+            Err(parser.errors)
+        }
+    }
+
+    /// Override values of the config file with the ones passed on the command line.
+    pub fn apply_args(&mut self, args: &CliArgs) {
+        if !args.listen_addresses.is_empty() {
+            self.server.listen.clone_from(&args.listen_addresses);
+        }
+        if let Some(port) = args.listen_port {
+            self.server.port = port;
+        }
+    }
+
+    /// Convert the configuration back into a [`KdlDocument`], including all defaults.
+    ///
+    /// The password hashes of `basic_auth_users` are replaced by `<redacted>`.
+    pub fn to_kdl(&self) -> KdlDocument {
+        let m = &self.metrics;
+        let mut metrics = KdlNode::new("metrics");
+        metrics.insert("timeout_ms", millis(m.timeout));
+        metrics.insert("interval_ms", millis(m.interval));
+
+        let mut cpu_seconds = metric_node(
+            "cpu_seconds",
+            m.cpu_seconds.enabled,
+            m.cpu_seconds.timeout,
+            m.cpu_seconds.interval,
+        );
+        cpu_seconds.insert("period_ms", millis(m.cpu_seconds.period));
+
+        let children = metrics.ensure_children().nodes_mut();
+        children.push(cpu_seconds);
+        children.push(metric_node(
+            "loadavg",
+            m.loadavg.enabled,
+            m.loadavg.timeout,
+            m.loadavg.interval,
+        ));
+        children.push(with_list(
+            metric_node(
+                "memory_used",
+                m.memory_used.enabled,
+                m.memory_used.timeout,
+                m.memory_used.interval,
+            ),
+            "fields",
+            &m.memory_used.fields,
+        ));
+        children.push(with_list(
+            metric_node(
+                "systemd_unit_state",
+                m.systemd_unit_state.enabled,
+                m.systemd_unit_state.timeout,
+                m.systemd_unit_state.interval,
+            ),
+            "units",
+            &m.systemd_unit_state.units,
+        ));
+        children.push(with_list(
+            metric_node(
+                "network_throughput",
+                m.network_throughput.enabled,
+                m.network_throughput.timeout,
+                m.network_throughput.interval,
+            ),
+            "interfaces",
+            &m.network_throughput.interfaces,
+        ));
+        children.push(with_list(
+            metric_node(
+                "disk_usage",
+                m.disk_usage.enabled,
+                m.disk_usage.timeout,
+                m.disk_usage.interval,
+            ),
+            "mountpoints",
+            &m.disk_usage.mountpoints,
+        ));
+        children.push(metric_node(
+            "pressure",
+            m.pressure.enabled,
+            m.pressure.timeout,
+            m.pressure.interval,
+        ));
+        children.push(with_list(
+            metric_node(
+                "disk_stats",
+                m.disk_stats.enabled,
+                m.disk_stats.timeout,
+                m.disk_stats.interval,
+            ),
+            "mountpoints",
+            &m.disk_stats.mountpoints,
+        ));
+        children.push(with_list(
+            metric_node(
+                "vmstat",
+                m.vmstat.enabled,
+                m.vmstat.timeout,
+                m.vmstat.interval,
+            ),
+            "fields",
+            &m.vmstat.fields,
+        ));
+
+        let mut doc = KdlDocument::new();
+        doc.nodes_mut().push(self.server.to_kdl());
+        doc.nodes_mut().push(self.log.to_kdl());
+        doc.nodes_mut().push(metrics);
+        doc.autoformat();
+        doc
+    }
+}
+
+impl Display for UserConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_kdl())
+    }
+}
+
+/// Placeholder for secrets in [`UserConfig::to_kdl`].
+const REDACTED: &str = "<redacted>";
+
+/// Duration in milliseconds, as stored in the config file.
+fn millis(duration: Duration) -> i128 {
+    i128::try_from(duration.as_millis()).unwrap_or(i128::MAX)
+}
+
+/// Node with a single argument, e.g., `port 9774`.
+fn arg_node(name: &str, value: impl Into<KdlEntry>) -> KdlNode {
+    let mut node = KdlNode::new(name);
+    node.push(value);
+    node
+}
+
+/// Node for a metric with the properties shared by all metrics.
+fn metric_node(
+    name: &str,
+    enabled: bool,
+    timeout: Option<Duration>,
+    interval: Option<Duration>,
+) -> KdlNode {
+    let mut node = KdlNode::new(name);
+    node.insert("enabled", enabled);
+    if let Some(timeout) = timeout {
+        node.insert("timeout_ms", millis(timeout));
+    }
+    if let Some(interval) = interval {
+        node.insert("interval_ms", millis(interval));
+    }
+
+    node
+}
+
+/// Add the child `list` with `values` as arguments to `node`, unless `values` is empty.
+fn with_list(mut node: KdlNode, list: &str, values: &[String]) -> KdlNode {
+    if values.is_empty() {
+        return node;
+    }
+
+    let mut child = KdlNode::new(list);
+    for value in values {
+        child.push(value.as_str());
+    }
+    node.ensure_children().nodes_mut().push(child);
+
+    node
+}
+
+/// Extracts the configuration from a [`KdlDocument`], collecting all problems on the way.
+struct Parser<'a> {
+    /// Source of the document. Used to turn offsets into lines and columns.
+    source: &'a str,
+    errors: Vec<ConfigError>,
+}
+
+impl Parser<'_> {
+    /// Record an error at the byte `offset` into the source.
+    fn error(&mut self, offset: usize, message: String) {
+        let before = self.source.get(..offset).unwrap_or(self.source);
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map_or(0, |last| last.chars().count())
+            + 1;
+        self.errors.push(ConfigError {
+            line,
+            column,
+            message,
+        });
+    }
+
+    /// Check that `doc` only contains nodes named in `allowed`, each at most once.
+    fn check_children(&mut self, doc: &KdlDocument, parent: &str, allowed: &[&str]) {
+        let mut seen: Vec<&str> = Vec::new();
+        for child in doc.nodes() {
+            let name = child.name().value();
+            if !allowed.contains(&name) {
+                let message = if allowed.is_empty() {
+                    format!("unexpected node `{name}` in {parent}")
+                } else {
+                    format!(
+                        "unknown node `{name}` in {parent}, expected one of: {}",
+                        allowed.join(", ")
+                    )
+                };
+                self.error(child.name().span().offset(), message);
+            } else if seen.contains(&name) {
+                self.error(
+                    child.name().span().offset(),
+                    format!("duplicate node `{name}` in {parent}"),
+                );
+            } else {
+                seen.push(name);
+            }
+        }
+    }
 
-                if let Some(node) = children.get("disk_stats") {
-                    let enabled = node
-                        .get("enabled")
-                        .and_then(|el| el.as_bool())
-                        .unwrap_or_default();
-                    let mountpoints = node
-                        .children()
-                        .and_then(|el| el.get("mountpoints"))
-                        .map(|el| el.entries())
-                        .map(|it| {
-                            it.iter()
-                                .filter_map(|el| el.value().as_string())
-                                .map(ToOwned::to_owned)
-                                .collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    ret.disk_stats = DiskStatConfig {
-                        enabled,
-                        mountpoints,
+    /// Check that `node` only has the properties in `props`, the children in `children`, and
+    /// positional arguments only if `args` is set.
+    fn check_node(&mut self, node: &KdlNode, args: bool, props: &[&str], children: &[&str]) {
+        let name = node.name().value();
+        for entry in node.entries() {
+            match entry.name() {
+                Some(prop) if !props.contains(&prop.value()) => {
+                    let message = if props.is_empty() {
+                        format!("unexpected property `{}` on `{name}`", prop.value())
+                    } else {
+                        format!(
+                            "unknown property `{}` on `{name}`, expected one of: {}",
+                            prop.value(),
+                            props.join(", ")
+                        )
                     };
+                    self.error(entry.span().offset(), message);
+                }
+                None if !args => {
+                    self.error(
+                        entry.span().offset(),
+                        format!("unexpected argument `{}` on `{name}`", entry.value()),
+                    );
                 }
+                _ => {}
             }
+        }
+
+        if let Some(doc) = node.children() {
+            self.check_children(doc, &format!("`{name}`"), children);
+        }
+    }
+
+    /// Boolean property `prop` of `node`.
+    fn bool_prop(&mut self, node: &KdlNode, prop: &str) -> Option<bool> {
+        let entry = node.entry(prop)?;
+        let ret = entry.value().as_bool();
+        if ret.is_none() {
+            self.error(
+                entry.span().offset(),
+                format!("`{prop}` must be #true or #false"),
+            );
+        }
+
+        ret
+    }
+
+    /// Non-negative integer property `prop` of `node`, in milliseconds.
+    fn millis_prop(&mut self, node: &KdlNode, prop: &str) -> Option<Duration> {
+        let entry = node.entry(prop)?;
+        let ret = entry
+            .value()
+            .as_integer()
+            .and_then(|ms| u64::try_from(ms).ok())
+            .map(Duration::from_millis);
+        if ret.is_none() {
+            self.error(
+                entry.span().offset(),
+                format!("`{prop}` must be a non-negative integer (milliseconds)"),
+            );
+        }
+
+        ret
+    }
+
+    /// Positive integer property `prop` of `node`, in milliseconds.
+    fn positive_millis_prop(&mut self, node: &KdlNode, prop: &str) -> Option<Duration> {
+        let ret = self.millis_prop(node, prop)?;
+        if ret.is_zero() {
+            let offset = node.entry(prop).map_or(0, |el| el.span().offset());
+            self.error(offset, format!("`{prop}` must be greater than 0"));
+            return None;
+        }
+
+        Some(ret)
+    }
 
-            ret
+    /// The `timeout_ms` and `interval_ms` properties of a metric, which override the defaults in
+    /// `metrics`.
+    fn schedule(
+        &mut self,
+        node: &KdlNode,
+        metrics: &MetricsConfig,
+    ) -> (Option<Duration>, Option<Duration>) {
+        let timeout = self.positive_millis_prop(node, "timeout_ms");
+        let interval = self.positive_millis_prop(node, "interval_ms");
+        if timeout.is_some() || interval.is_some() {
+            let timeout = timeout.unwrap_or(metrics.timeout);
+            let interval = interval.unwrap_or(metrics.interval);
+            if timeout >= interval {
+                self.error(
+                    node.span().offset(),
+                    format!(
+                        "`timeout_ms` ({}) of `{}` must be less than its `interval_ms` ({})",
+                        timeout.as_millis(),
+                        node.name().value(),
+                        interval.as_millis()
+                    ),
+                );
+            }
+        }
+
+        (timeout, interval)
+    }
+
+    /// The `enabled` property of a metric. Defaults to `false`.
+    fn enabled(&mut self, node: &KdlNode) -> bool {
+        self.bool_prop(node, "enabled").unwrap_or_default()
+    }
+
+    /// String arguments of the child `list` of `node`, e.g., `units "a.service" "b.service"`.
+    fn string_list(&mut self, node: &KdlNode, list: &str) -> Vec<String> {
+        let Some(child) = node.children().and_then(|el| el.get(list)) else {
+            return Vec::new();
         };
+        self.check_node(child, true, &[], &[]);
 
-        let metrics = doc
-            .get("metrics")
-            .map_or_else(MetricsConfig::default, extract_metrics);
-        let ret = Self { metrics };
+        let mut ret = Vec::new();
+        for entry in child.entries().iter().filter(|el| el.name().is_none()) {
+            match entry.value().as_string() {
+                Some(value) => ret.push(value.to_owned()),
+                None => self.error(
+                    entry.span().offset(),
+                    format!("values of `{list}` must be strings"),
+                ),
+            }
+        }
+
+        ret
+    }
+
+    /// Check the values of the child `fields` of `node` with `validate`, and that none of them
+    /// is repeated.
+    fn check_fields<E: Display>(
+        &mut self,
+        node: &KdlNode,
+        validate: impl Fn(&str) -> Result<(), E>,
+    ) {
+        let Some(list) = node.children().and_then(|el| el.get("fields")) else {
+            return;
+        };
+
+        let mut seen: Vec<&str> = Vec::new();
+        for entry in list.entries() {
+            let Some(field) = entry.value().as_string() else {
+                continue;
+            };
+            if let Err(err) = validate(field) {
+                self.error(entry.span().offset(), err.to_string());
+            } else if seen.contains(&field) {
+                self.error(
+                    entry.span().offset(),
+                    format!("duplicate field `{field}` in `fields`"),
+                );
+            } else {
+                seen.push(field);
+            }
+        }
+    }
+
+    /// The single argument of `node`, e.g., `port 9774`.
+    fn arg<'n>(&mut self, node: &'n KdlNode) -> Option<&'n KdlEntry> {
+        self.check_node(node, true, &[], &[]);
+
+        let name = node.name().value();
+        let mut args = node.entries().iter().filter(|el| el.name().is_none());
+        let Some(arg) = args.next() else {
+            self.error(node.span().offset(), format!("`{name}` requires a value"));
+            return None;
+        };
+        if let Some(extra) = args.next() {
+            self.error(
+                extra.span().offset(),
+                format!("`{name}` takes a single value"),
+            );
+        }
+
+        Some(arg)
+    }
+
+    /// The single string argument of `node`.
+    fn string_arg(&mut self, node: &KdlNode) -> Option<String> {
+        let arg = self.arg(node)?;
+        let ret = arg.value().as_string().map(ToOwned::to_owned);
+        if ret.is_none() {
+            self.error(
+                arg.span().offset(),
+                format!("`{}` must be a string", node.name().value()),
+            );
+        }
+
+        ret
+    }
+
+    /// The single argument of the child `name` of `node`.
+    fn child_arg<'n>(&mut self, node: &'n KdlNode, name: &str) -> Option<&'n KdlEntry> {
+        let child = node.children()?.get(name)?;
+        self.arg(child)
+    }
+
+    /// The single string argument of the child `name` of `node`.
+    fn child_string(&mut self, node: &KdlNode, name: &str) -> Option<String> {
+        let child = node.children()?.get(name)?;
+        self.string_arg(child)
+    }
+
+    fn server(&mut self, node: &KdlNode) -> ServerConfig {
+        self.check_node(
+            node,
+            false,
+            &[],
+            &[
+                "listen",
+                "port",
+                "grace_period_ms",
+                "tls",
+                "auth",
+                "allow_ips",
+                "deny_ips",
+                "socket",
+            ],
+        );
+
+        let mut ret = ServerConfig::default();
+        if let Some(arg) = self.child_arg(node, "port") {
+            match arg
+                .value()
+                .as_integer()
+                .and_then(|el| u16::try_from(el).ok())
+            {
+                Some(port) => ret.port = port,
+                None => self.error(
+                    arg.span().offset(),
+                    "`port` must be an integer between 0 and 65535".to_owned(),
+                ),
+            }
+        }
+        if let Some(listen) = node.children().and_then(|el| el.get("listen")) {
+            ret.listen = self.listen(listen);
+        }
+        if let Some(arg) = self.child_arg(node, "grace_period_ms") {
+            match arg
+                .value()
+                .as_integer()
+                .and_then(|el| u64::try_from(el).ok())
+            {
+                Some(ms) => ret.grace_period = Duration::from_millis(ms),
+                None => self.error(
+                    arg.span().offset(),
+                    "`grace_period_ms` must be a non-negative integer (milliseconds)".to_owned(),
+                ),
+            }
+        }
+        if let Some(tls) = node.children().and_then(|el| el.get("tls")) {
+            ret.tls = self.tls(tls);
+        }
+        if let Some(auth) = node.children().and_then(|el| el.get("auth")) {
+            ret.auth = Some(self.auth(auth));
+        }
+        ret.allow_ips = self.networks(node, "allow_ips");
+        ret.deny_ips = self.networks(node, "deny_ips");
+        if let Some(socket) = node.children().and_then(|el| el.get("socket")) {
+            ret.socket = self.socket(socket);
+        }
+
+        ret
+    }
+
+    /// The addresses of the `listen` node, e.g., `listen "127.0.0.1" "[::1]:9100"`.
+    fn listen(&mut self, node: &KdlNode) -> Vec<String> {
+        self.check_node(node, true, &[], &[]);
+
+        let mut ret = Vec::new();
+        let mut entries = node
+            .entries()
+            .iter()
+            .filter(|el| el.name().is_none())
+            .peekable();
+        if entries.peek().is_none() {
+            self.error(node.span().offset(), "`listen` requires a value".to_owned());
+        }
+        for entry in entries {
+            let Some(value) = entry.value().as_string() else {
+                self.error(
+                    entry.span().offset(),
+                    "values of `listen` must be strings".to_owned(),
+                );
+                continue;
+            };
+            // The port doesn't matter for validation.
+            match ListenAddr::parse(value, 0) {
+                Ok(_) => ret.push(value.to_owned()),
+                Err(err) => self.error(entry.span().offset(), err.to_string()),
+            }
+        }
+
+        ret
+    }
+
+    fn socket(&mut self, node: &KdlNode) -> SocketConfig {
+        self.check_node(node, false, &[], &["mode", "owner", "group"]);
+
+        let mut ret = SocketConfig::default();
+        if let Some(child) = node.children().and_then(|el| el.get("mode"))
+            && let Some(mode) = self.string_arg(child)
+        {
+            match u32::from_str_radix(&mode, 8) {
+                Ok(mode) if mode <= 0o777 => ret.mode = Some(mode),
+                _ => self.error(
+                    child.span().offset(),
+                    "`mode` must be an octal file mode, e.g., \"0660\"".to_owned(),
+                ),
+            }
+        }
+        ret.owner = self.child_string(node, "owner");
+        ret.group = self.child_string(node, "group");
+
+        ret
+    }
+
+    fn tls(&mut self, node: &KdlNode) -> Option<TlsConfig> {
+        self.check_node(
+            node,
+            false,
+            &[],
+            &["cert", "key", "client_ca", "allowed_names"],
+        );
+
+        let cert = self.child_path(node, "cert");
+        let key = self.child_path(node, "key");
+        let client_ca = self.child_string(node, "client_ca").map(PathBuf::from);
+        let allowed_names = self.string_list(node, "allowed_names");
+        if let Some(child) = node.children().and_then(|el| el.get("allowed_names"))
+            && client_ca.is_none()
+        {
+            self.error(
+                child.span().offset(),
+                "`allowed_names` requires `client_ca`".to_owned(),
+            );
+        }
+
+        Some(TlsConfig {
+            cert: cert?,
+            key: key?,
+            client_ca,
+            allowed_names,
+        })
+    }
+
+    /// The networks in the child `list` of `node`. Plain addresses are treated as single-host
+    /// networks.
+    fn networks(&mut self, node: &KdlNode, list: &str) -> Vec<IpNet> {
+        let Some(child) = node.children().and_then(|el| el.get(list)) else {
+            return Vec::new();
+        };
+        self.check_node(child, true, &[], &[]);
+
+        let mut ret = Vec::new();
+        for entry in child.entries().iter().filter(|el| el.name().is_none()) {
+            let net = entry.value().as_string().and_then(|value| {
+                value
+                    .parse::<IpNet>()
+                    .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+                    .ok()
+            });
+            match net {
+                Some(net) => ret.push(net),
+                None => self.error(
+                    entry.span().offset(),
+                    format!("values of `{list}` must be networks, e.g., \"10.0.0.0/8\""),
+                ),
+            }
+        }
+
+        ret
+    }
+
+    fn auth(&mut self, node: &KdlNode) -> AuthConfig {
+        self.check_node(node, false, &[], &["basic_auth_users", "bearer_token_file"]);
+        // Children of `basic_auth_users` are usernames, so only its entries are checked here.
+        let users_node = node.children().and_then(|el| el.get("basic_auth_users"));
+        for entry in users_node.map(KdlNode::entries).unwrap_or_default() {
+            self.error(
+                entry.span().offset(),
+                format!(
+                    "unexpected argument `{}` on `basic_auth_users`",
+                    entry.value()
+                ),
+            );
+        }
+
+        let mut basic_auth_users: Vec<(String, String)> = Vec::new();
+        let users = users_node
+            .and_then(KdlNode::children)
+            .map(KdlDocument::nodes);
+        for user in users.unwrap_or_default() {
+            let username = user.name().value();
+            if basic_auth_users.iter().any(|(el, _)| el == username) {
+                self.error(
+                    user.span().offset(),
+                    format!("duplicate user `{username}` in `basic_auth_users`"),
+                );
+                continue;
+            }
+            let Some(hash) = self.string_arg(user) else {
+                continue;
+            };
+            match crate::auth::validate_hash(&hash) {
+                Ok(()) => basic_auth_users.push((username.to_owned(), hash)),
+                Err(err) => self.error(
+                    user.span().offset(),
+                    format!("invalid password hash for user `{username}`: {err}"),
+                ),
+            }
+        }
+        let bearer_token_file = self
+            .child_string(node, "bearer_token_file")
+            .map(PathBuf::from);
+
+        // An empty `basic_auth_users` block would reject every request.
+        let has_users = users.is_some_and(|el| !el.is_empty());
+        if !has_users && bearer_token_file.is_none() {
+            self.error(
+                node.span().offset(),
+                "`auth` requires users in `basic_auth_users` or `bearer_token_file`".to_owned(),
+            );
+        }
+
+        AuthConfig {
+            basic_auth_users,
+            bearer_token_file,
+        }
+    }
+
+    /// The single path argument of the required child `name` of `node`.
+    fn child_path(&mut self, node: &KdlNode, name: &str) -> Option<PathBuf> {
+        let has_child = node.children().is_some_and(|el| el.get(name).is_some());
+        if !has_child {
+            self.error(
+                node.span().offset(),
+                format!("`{}` requires `{name}`", node.name().value()),
+            );
+            return None;
+        }
+
+        self.child_string(node, name).map(PathBuf::from)
+    }
+
+    fn log(&mut self, node: &KdlNode) -> LogConfig {
+        self.check_node(node, false, &[], &["level", "format"]);
+
+        let mut ret = LogConfig::default();
+        if let Some(level) = self.child_string(node, "level") {
+            match tracing_subscriber::EnvFilter::try_new(&level) {
+                Ok(_) => ret.level = Some(level),
+                Err(err) => {
+                    let offset = self
+                        .child_arg(node, "level")
+                        .map_or(0, |el| el.span().offset());
+                    self.error(offset, format!("invalid log level `{level}`: {err}"));
+                }
+            }
+        }
+        if let Some(format) = self.child_string(node, "format") {
+            match format.parse() {
+                Ok(format) => ret.format = format,
+                Err(err) => {
+                    let offset = self
+                        .child_arg(node, "format")
+                        .map_or(0, |el| el.span().offset());
+                    self.error(offset, format!("{err}"));
+                }
+            }
+        }
+
+        ret
+    }
+
+    #[allow(clippy::too_many_lines, clippy::shadow_unrelated)]
+    fn metrics(&mut self, node: &KdlNode) -> MetricsConfig {
+        self.check_node(
+            node,
+            false,
+            &["timeout_ms", "interval_ms"],
+            &[
+                "cpu_seconds",
+                "loadavg",
+                "memory_used",
+                "systemd_unit_state",
+                "network_throughput",
+                "disk_usage",
+                "pressure",
+                "disk_stats",
+                "vmstat",
+            ],
+        );
+
+        let mut ret = MetricsConfig::default();
+        if let Some(timeout) = self.positive_millis_prop(node, "timeout_ms") {
+            ret.timeout = timeout;
+        }
+        if let Some(interval) = self.positive_millis_prop(node, "interval_ms") {
+            ret.interval = interval;
+        }
+        if ret.timeout >= ret.interval {
+            self.error(
+                node.span().offset(),
+                format!(
+                    "`timeout_ms` ({}) must be less than `interval_ms` ({})",
+                    ret.timeout.as_millis(),
+                    ret.interval.as_millis()
+                ),
+            );
+        }
+
+        // Without children, all metrics keep their defaults, which still have to be checked
+        // against the timeout above.
+        let empty = KdlDocument::new();
+        let children = node.children().unwrap_or(&empty);
+
+        if let Some(node) = children.get("cpu_seconds") {
+            self.check_node(
+                node,
+                false,
+                &["enabled", "period_ms", "timeout_ms", "interval_ms"],
+                &[],
+            );
+            let (timeout, interval) = self.schedule(node, &ret);
+            let period = self
+                .positive_millis_prop(node, "period_ms")
+                .unwrap_or(ret.cpu_seconds.period);
+            ret.cpu_seconds = CpuSecondsConfig {
+                enabled: self.enabled(node),
+                period,
+                timeout,
+                interval,
+            };
+        }
+
+        // The collector sleeps for `period_ms`, which has to fit into the timeout. Checked on the
+        // effective values, as the default period may exceed a short timeout on `metrics`.
+        let cpu_seconds = &ret.cpu_seconds;
+        let effective_timeout = cpu_seconds.timeout.unwrap_or(ret.timeout);
+        if cpu_seconds.enabled && cpu_seconds.period >= effective_timeout {
+            let offset = children.get("cpu_seconds").unwrap_or(node).span().offset();
+            self.error(
+                offset,
+                format!(
+                    "`period_ms` ({}) of `cpu_seconds` must be less than its `timeout_ms` ({})",
+                    cpu_seconds.period.as_millis(),
+                    effective_timeout.as_millis()
+                ),
+            );
+        }
+
+        if let Some(node) = children.get("loadavg") {
+            self.check_node(node, false, METRIC_PROPS, &[]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.loadavg = LoadAvgConfig {
+                enabled: self.enabled(node),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("memory_used") {
+            self.check_node(node, false, METRIC_PROPS, &["fields"]);
+            let fields = self.string_list(node, "fields");
+            self.check_fields(node, |el| el.parse::<MeminfoField>().map(drop));
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.memory_used = MemoryUsedConfig {
+                enabled: self.enabled(node),
+                fields,
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("systemd_unit_state") {
+            self.check_node(node, false, METRIC_PROPS, &["units"]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.systemd_unit_state = SystemdUnitStateConfig {
+                enabled: self.enabled(node),
+                units: self.string_list(node, "units"),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("network_throughput") {
+            self.check_node(node, false, METRIC_PROPS, &["interfaces"]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.network_throughput = NetworkThroughputConfig {
+                enabled: self.enabled(node),
+                interfaces: self.string_list(node, "interfaces"),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("disk_usage") {
+            self.check_node(node, false, METRIC_PROPS, &["mountpoints"]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.disk_usage = DiskUsageConfig {
+                enabled: self.enabled(node),
+                mountpoints: self.string_list(node, "mountpoints"),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("pressure") {
+            self.check_node(node, false, METRIC_PROPS, &[]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.pressure = PressureConfig {
+                enabled: self.enabled(node),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("disk_stats") {
+            self.check_node(node, false, METRIC_PROPS, &["mountpoints"]);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.disk_stats = DiskStatConfig {
+                enabled: self.enabled(node),
+                mountpoints: self.string_list(node, "mountpoints"),
+                timeout,
+                interval,
+            };
+        }
+
+        if let Some(node) = children.get("vmstat") {
+            self.check_node(node, false, METRIC_PROPS, &["fields"]);
+            let fields = self.string_list(node, "fields");
+            self.check_fields(node, vmstat::validate_field);
+            let (timeout, interval) = self.schedule(node, &ret);
+            ret.vmstat = VmStatConfig {
+                enabled: self.enabled(node),
+                fields,
+                timeout,
+                interval,
+            };
+        }
 
-        Ok(ret)
+        ret
     }
 }
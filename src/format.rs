@@ -0,0 +1,344 @@
+//! Exposition formats for serving metrics.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use hashbrown::HashMap;
+use http::{HeaderMap, HeaderValue, header};
+use serde_json::{Map, Value, json};
+
+use crate::protobuf::{self, MetricFamily};
+
+/// Exposition format of a scrape response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// OpenMetrics 1.0 text, as produced by the registry.
+    OpenMetrics,
+    /// Classic Prometheus text format 0.0.4.
+    PrometheusText,
+    /// Prometheus protobuf format, as delimited `io.prometheus.client.MetricFamily` messages.
+    Protobuf,
+    /// JSON array of all families, for scripts.
+    Json,
+}
+
+impl Format {
+    /// Pick the format for the response based on the `Accept` header in `headers`.
+    ///
+    /// OpenMetrics, protobuf and JSON are only served if asked for explicitly, everything else
+    /// gets the Prometheus text format, which every scraper understands.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let mut json = 0.0;
+        let mut protobuf = 0.0;
+        let mut openmetrics = 0.0;
+        let mut text = 0.0;
+        let ranges = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|el| el.to_str().ok())
+            .flat_map(|el| el.split(','))
+            .filter_map(MediaRange::parse);
+        for range in ranges {
+            let version = range.param("version");
+            if range.is("application", "json") {
+                json = f32::max(json, range.q);
+            } else if range.is("application", "vnd.google.protobuf")
+                && range.param("proto") == Some("io.prometheus.client.MetricFamily")
+                && range.param("encoding") == Some("delimited")
+            {
+                protobuf = f32::max(protobuf, range.q);
+            } else if range.is("application", "openmetrics-text")
+                && version.is_none_or(|el| el == "1.0.0" || el == "0.0.1")
+            {
+                openmetrics = f32::max(openmetrics, range.q);
+            } else if (range.is("text", "plain") || range.is("text", "*") || range.is("*", "*"))
+                && version.is_none_or(|el| el == "0.0.4")
+            {
+                text = f32::max(text, range.q);
+            }
+        }
+
+        if json > 0.0 && json >= protobuf && json >= openmetrics && json >= text {
+            Self::Json
+        } else if protobuf > 0.0 && protobuf >= openmetrics && protobuf >= text {
+            Self::Protobuf
+        } else if openmetrics > 0.0 && openmetrics >= text {
+            Self::OpenMetrics
+        } else {
+            Self::PrometheusText
+        }
+    }
+
+    /// Value of the `Content-Type` header.
+    pub const fn content_type(self) -> HeaderValue {
+        match self {
+            Self::OpenMetrics => HeaderValue::from_static(
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            ),
+            Self::PrometheusText => {
+                HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8")
+            }
+            Self::Protobuf => HeaderValue::from_static(
+                "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited",
+            ),
+            Self::Json => HeaderValue::from_static("application/json"),
+        }
+    }
+
+    /// Whether the format is converted from the OpenMetrics text of the registries, see
+    /// [`Self::encode`], rather than encoded from metric families, see [`Self::encode_families`].
+    pub const fn is_text(self) -> bool {
+        matches!(self, Self::OpenMetrics | Self::PrometheusText)
+    }
+
+    /// Convert `openmetrics` text, as produced by the registry, into this text format.
+    pub fn encode(self, openmetrics: String) -> Result<Vec<u8>> {
+        match self {
+            Self::OpenMetrics => Ok(openmetrics.into_bytes()),
+            Self::PrometheusText => Ok(to_prometheus_text(&openmetrics)?.into_bytes()),
+            Self::Protobuf | Self::Json => anyhow::bail!("{self:?} is not a text format"),
+        }
+    }
+
+    /// Encode `families`, as produced by [`crate::collector::Collector::families`], into this
+    /// format.
+    pub fn encode_families(self, families: &[MetricFamily]) -> Result<Vec<u8>> {
+        match self {
+            Self::Protobuf => protobuf::encode_delimited(families),
+            Self::Json => to_json(families),
+            Self::OpenMetrics | Self::PrometheusText => {
+                anyhow::bail!("{self:?} is converted from OpenMetrics text")
+            }
+        }
+    }
+}
+
+/// One element of an `Accept` header, e.g., `text/plain; version=0.0.4; q=0.5`.
+struct MediaRange<'a> {
+    kind: &'a str,
+    subtype: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+    q: f32,
+}
+
+impl<'a> MediaRange<'a> {
+    fn parse(element: &'a str) -> Option<Self> {
+        let mut parts = element.split(';').map(str::trim);
+        let (kind, subtype) = parts.next()?.split_once('/')?;
+
+        let mut params = Vec::new();
+        let mut q = 1.0;
+        for (key, value) in parts.filter_map(|el| el.split_once('=')) {
+            let value = value.trim().trim_matches('"');
+            if key.trim().eq_ignore_ascii_case("q") {
+                q = value.parse().ok()?;
+            } else {
+                params.push((key.trim(), value));
+            }
+        }
+
+        Some(Self {
+            kind,
+            subtype,
+            params,
+            q,
+        })
+    }
+
+    /// Whether the range is exactly `kind/subtype`.
+    fn is(&self, kind: &str, subtype: &str) -> bool {
+        self.kind.eq_ignore_ascii_case(kind) && self.subtype.eq_ignore_ascii_case(subtype)
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Convert OpenMetrics text into the Prometheus text format 0.0.4.
+///
+/// Counter families get their `_total` suffix back, types unknown to 0.0.4 are mapped to the
+/// closest equivalent, and `# EOF`, `# UNIT`, `_created` samples and exemplars are dropped.
+fn to_prometheus_text(openmetrics: &str) -> Result<String, std::fmt::Error> {
+    // HELP comes before TYPE, so look up all types first.
+    let types = openmetrics
+        .lines()
+        .filter_map(|el| el.strip_prefix("# TYPE "))
+        .filter_map(|el| el.split_once(' '))
+        .collect::<HashMap<_, _>>();
+
+    let mut ret = String::with_capacity(openmetrics.len());
+    let mut family = ("", "");
+    for line in openmetrics.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let mut parts = comment.splitn(3, ' ');
+            let (Some(kind), Some(om_name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let value = parts.next().unwrap_or_default();
+            let om_type = types.get(om_name).copied().unwrap_or("unknown");
+            let (name, prom_type) = family_name(om_name, om_type);
+            match kind {
+                "HELP" => {
+                    // OpenMetrics escapes quotes in HELP, the Prometheus format does not.
+                    let help = value.replace(r#"\""#, "\"");
+                    writeln!(ret, "# HELP {name} {help}")?;
+                }
+                "TYPE" => {
+                    family = (om_name, om_type);
+                    writeln!(ret, "# TYPE {name} {prom_type}")?;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let (name, om_type) = family;
+        let sample_name = line.split(['{', ' ']).next().unwrap_or_default();
+        let has_created = matches!(om_type, "counter" | "histogram" | "summary");
+        if has_created && sample_name.strip_suffix("_created") == Some(name) {
+            continue;
+        }
+
+        ret.push_str(strip_exemplar(line));
+        ret.push('\n');
+    }
+
+    Ok(ret)
+}
+
+/// Convert `families` into a JSON array.
+///
+/// Each family has `name`, `type`, `help` and `metrics`, using the same names as the Prometheus
+/// text format. Families without any samples are left out. Each metric has `labels` and either
+/// `value`, or `buckets`, `count` and `sum` for histograms, or `quantiles`, `count` and `sum` for
+/// summaries.
+fn to_json(families: &[MetricFamily]) -> Result<Vec<u8>> {
+    let families = families
+        .iter()
+        // E.g., label families of units or interfaces that don't exist.
+        .filter(|family| !family.metric.is_empty())
+        .map(|family| {
+            let metrics = family.metric.iter().map(json_metric).collect::<Vec<_>>();
+            json!({
+                "name": family.name(),
+                "type": family.r#type().name(),
+                "help": family.help(),
+                "metrics": metrics,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(serde_json::to_vec(&families)?)
+}
+
+/// JSON object of a single metric of a family.
+fn json_metric(metric: &protobuf::Metric) -> Value {
+    let labels = metric
+        .label
+        .iter()
+        .map(|el| (el.name().to_owned(), Value::from(el.value())))
+        .collect::<Map<_, _>>();
+    let mut ret = json!({ "labels": labels });
+
+    let value = metric
+        .counter
+        .as_ref()
+        .map(protobuf::Counter::value)
+        .or_else(|| metric.gauge.as_ref().map(protobuf::Gauge::value))
+        .or_else(|| metric.untyped.as_ref().map(protobuf::Untyped::value));
+    if let Some(value) = value {
+        ret["value"] = json_float(value);
+    }
+    if let Some(histogram) = &metric.histogram {
+        let buckets = histogram
+            .bucket
+            .iter()
+            .map(|el| {
+                (
+                    float_string(el.upper_bound()),
+                    Value::from(el.cumulative_count()),
+                )
+            })
+            .collect::<Map<_, _>>();
+        ret["buckets"] = Value::Object(buckets);
+        ret["count"] = Value::from(histogram.sample_count());
+        ret["sum"] = json_float(histogram.sample_sum());
+    }
+    if let Some(summary) = &metric.summary {
+        let quantiles = summary
+            .quantile
+            .iter()
+            .map(|el| (float_string(el.quantile()), json_float(el.value())))
+            .collect::<Map<_, _>>();
+        ret["quantiles"] = Value::Object(quantiles);
+        ret["count"] = Value::from(summary.sample_count());
+        ret["sum"] = json_float(summary.sample_sum());
+    }
+
+    ret
+}
+
+/// `value` as JSON number, or as string like in the text formats if it is not finite.
+fn json_float(value: f64) -> Value {
+    if value.is_finite() {
+        Value::from(value)
+    } else {
+        Value::from(float_string(value))
+    }
+}
+
+/// `value` formatted like in the text formats, e.g., `0.5` or `+Inf`.
+fn float_string(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_owned()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_owned()
+    } else if value.is_nan() {
+        "NaN".to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Name and type of a family with OpenMetrics `name` and `om_type` in the Prometheus format.
+fn family_name(name: &str, om_type: &str) -> (String, &'static str) {
+    match om_type {
+        "counter" => (format!("{name}_total"), "counter"),
+        "gauge" | "stateset" => (name.to_owned(), "gauge"),
+        "histogram" => (name.to_owned(), "histogram"),
+        "summary" => (name.to_owned(), "summary"),
+        "info" => (format!("{name}_info"), "gauge"),
+        _ => (name.to_owned(), "untyped"),
+    }
+}
+
+/// Remove the exemplar (` # {...} value`) from a sample line.
+fn strip_exemplar(line: &str) -> &str {
+    // Skip the label set first, label values may contain ` # `.
+    let mut rest_start = line.find(['{', ' ']).unwrap_or(line.len());
+    if line.get(rest_start..).is_some_and(|el| el.starts_with('{')) {
+        let mut in_quotes = false;
+        let mut escaped = false;
+        for (idx, ch) in line.char_indices().skip_while(|(idx, _)| *idx < rest_start) {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = in_quotes,
+                '"' => in_quotes = !in_quotes,
+                '}' if !in_quotes => {
+                    rest_start = idx;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    line.get(rest_start..)
+        .and_then(|el| el.find(" # "))
+        .and_then(|idx| line.get(..rest_start + idx))
+        .unwrap_or(line)
+}
@@ -1,22 +1,64 @@
 //! Lightweight HTTP server for serving metrics.
 
 use std::convert::Infallible;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use http::{header, HeaderValue, StatusCode};
+use http::{HeaderMap, HeaderValue, StatusCode, header};
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper::service::service_fn;
-use hyper::{body::Incoming, Request, Response};
+use hyper::{Request, Response, body::Incoming};
+use nix::errno::Errno;
+use nix::unistd::{Gid, Group, Uid, User};
+use smol::channel::{Receiver, Sender};
+use smol::io::{AsyncRead, AsyncWrite};
 use smol_hyper::rt::{FuturesIo, SmolTimer};
+use socket2::{Domain, Protocol, Socket, Type};
 
+use crate::acl::IpFilter;
+use crate::auth::Auth;
 use crate::collector::Collector;
-use crate::http_utils::{internal_server_error, not_found};
+use crate::compression::Encoding;
+use crate::config::{ListenAddr, ServerConfig, SocketConfig};
+use crate::format::Format;
+use crate::http_utils::{
+    bad_request, html, internal_server_error, not_acceptable, not_found, query_pairs, text,
+    unauthorized,
+};
+use crate::systemd::{self, ListenSocket};
+use crate::tls::Tls;
 
-async fn serve_metrics(collector: &Collector) -> Result<Response<BoxBody<Bytes, Infallible>>> {
-    let metrics = collector.collect_and_encode().await?.into_bytes();
-    let buf = Bytes::from(metrics);
+/// Names of the collectors selected with `collect[]` parameters in `query`, like in
+/// node_exporter. Empty if all collectors should be included.
+fn selected_collectors(query: Option<&str>) -> Vec<String> {
+    query_pairs(query.unwrap_or_default())
+        .into_iter()
+        .filter(|(key, _)| key == "collect[]")
+        .map(|(_, value)| value)
+        .collect()
+}
+
+async fn serve_metrics(
+    collector: &Collector,
+    headers: &HeaderMap,
+    format: Format,
+    selected: &[String],
+) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+    let Some(encoding) = Encoding::negotiate(headers) else {
+        return Ok(not_acceptable());
+    };
+
+    let metrics = if format.is_text() {
+        format.encode(collector.encode(selected).await?)?
+    } else {
+        format.encode_families(&collector.families(selected).await?)?
+    };
+    let buf = Bytes::from(encoding.encode(metrics)?);
 
     let body = Full::new(buf).boxed();
     let mut res = Response::new(body);
@@ -25,19 +67,94 @@ async fn serve_metrics(collector: &Collector) -> Result<Response<BoxBody<Bytes,
         header::SERVER,
         HeaderValue::from_str(&format!("litemon/{}", env!("CARGO_PKG_VERSION")))?,
     );
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, format.content_type());
+    if let Some(content_encoding) = encoding.header_value() {
+        res.headers_mut()
+            .insert(header::CONTENT_ENCODING, content_encoding);
+    }
+    res.headers_mut().insert(
+        header::VARY,
+        HeaderValue::from_static("Accept, Accept-Encoding"),
+    );
 
     Ok(res)
 }
 
+/// HTML page listing the version and the enabled collectors, with a link to the metrics.
+async fn landing_page(collector: &Collector) -> String {
+    let collectors = collector
+        .names()
+        .await
+        .iter()
+        .map(|el| format!("      <li>{el}</li>\n"))
+        .collect::<String>();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><title>litemon</title></head>
+  <body>
+    <h1>litemon {version}</h1>
+    <p><a href="metrics">Metrics</a></p>
+    <h2>Collectors</h2>
+    <ul>
+{collectors}    </ul>
+  </body>
+</html>
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    )
+}
+
 async fn serve_request(
     collector: Collector,
+    auth: Option<Auth>,
     req: Request<Incoming>,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>> {
     use hyper::Method;
 
     match (req.method(), req.uri().path()) {
-        (&Method::GET, "/metrics") => {
-            let response = serve_metrics(&collector)
+        // Health checks are not authenticated, so load balancers can use them.
+        (&Method::GET, "/healthz") => Ok(text(StatusCode::OK, "ok\n".to_owned())),
+        (&Method::GET, "/readyz") => {
+            if collector.is_ready().await {
+                Ok(text(StatusCode::OK, "ready\n".to_owned()))
+            } else {
+                let message = "collectors not initialized yet\n".to_owned();
+                Ok(text(StatusCode::SERVICE_UNAVAILABLE, message))
+            }
+        }
+
+        (&Method::GET, "/") => {
+            if let Some(auth) = &auth
+                && !auth.is_authorized(req.headers()).await
+            {
+                return Ok(unauthorized(auth.challenges()));
+            }
+
+            Ok(html(landing_page(&collector).await))
+        }
+
+        (&Method::GET, path @ ("/metrics" | "/metrics.json")) => {
+            if let Some(auth) = &auth
+                && !auth.is_authorized(req.headers()).await
+            {
+                return Ok(unauthorized(auth.challenges()));
+            }
+
+            let selected = selected_collectors(req.uri().query());
+            let unknown = collector.unknown_collectors(&selected).await;
+            if !unknown.is_empty() {
+                let message = format!("unknown collectors: {}\n", unknown.join(", "));
+                return Ok(bad_request(message));
+            }
+
+            let format = if path == "/metrics.json" {
+                Format::Json
+            } else {
+                Format::negotiate(req.headers())
+            };
+            let response = serve_metrics(&collector, req.headers(), format, &selected)
                 .await
                 .inspect_err(|err| eprintln!("error serving metrics request: {err}"))
                 .unwrap_or_else(|_err| internal_server_error());
@@ -48,42 +165,469 @@ async fn serve_request(
     }
 }
 
-async fn handle_client(collector: Collector, stream: smol::net::TcpStream) -> anyhow::Result<()> {
-    let service = service_fn(move |req| serve_request(collector.clone(), req));
+/// Serve requests on `stream` until the client closes it. Once `stopping` is closed, finishes
+/// the request in progress, if any, and closes the connection.
+async fn handle_client<S>(
+    collector: Collector,
+    auth: Option<Auth>,
+    stream: S,
+    stopping: Receiver<()>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let service = service_fn(move |req| serve_request(collector.clone(), auth.clone(), req));
 
-    hyper::server::conn::http1::Builder::new()
+    let conn = hyper::server::conn::http1::Builder::new()
         .header_read_timeout(None)
         .timer(SmolTimer::new())
-        .serve_connection(FuturesIo::new(stream), service)
-        .await?;
+        .serve_connection(FuturesIo::new(stream), service);
+    let mut conn = std::pin::pin!(conn);
+    let finished = smol::future::or(async { Some(conn.as_mut().await) }, async {
+        stopping.recv().await.ok();
+        None
+    })
+    .await;
+    if let Some(res) = finished {
+        return Ok(res?);
+    }
+
+    conn.as_mut().graceful_shutdown();
+    conn.await?;
 
     Ok(())
 }
 
-/// Serves the metrics endpoint.
-pub async fn listen(
+/// Time after which a TLS handshake that has not completed is aborted.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time to wait before accepting again after accepting a connection failed, e.g., because the
+/// process ran out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Perform the TLS handshake, if enabled, before serving the connection.
+///
+/// The handshake is aborted after [`TLS_HANDSHAKE_TIMEOUT`], or right away when shutting down.
+async fn handle_connection<S>(
     collector: Collector,
-    listen_addr: &str,
-    listen_port: u16,
-) -> anyhow::Result<()> {
-    let addr: std::net::IpAddr = listen_addr
-        .parse()
-        .with_context(|| format!("parsing listen addr: {listen_addr}"))?;
-    tracing::info!("listening on {addr}:{listen_port}");
-
-    let listener = smol::net::TcpListener::bind((addr, listen_port))
-        .await
-        .with_context(|| format!("bind to {addr}"))?;
+    tls: Option<Tls>,
+    auth: Option<Auth>,
+    stream: S,
+    stopping: Receiver<()>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match tls {
+        Some(tls) => {
+            let handshake = smol::future::or(async { Some(tls.accept(stream).await) }, async {
+                stopping.recv().await.ok();
+                None
+            });
+            let timeout = async {
+                smol::Timer::after(TLS_HANDSHAKE_TIMEOUT).await;
+                Some(Err(anyhow::anyhow!(
+                    "TLS handshake timed out after {}s",
+                    TLS_HANDSHAKE_TIMEOUT.as_secs()
+                )))
+            };
+            let Some(accepted) = smol::future::or(handshake, timeout).await else {
+                return Ok(());
+            };
+            handle_client(collector, auth, accepted?, stopping).await
+        }
+        None => handle_client(collector, auth, stream, stopping).await,
+    }
+}
 
-    loop {
-        let (stream, _addr) = listener.accept().await.context("accepting connection")?;
+/// State shared by all connections.
+#[derive(Debug, Clone)]
+struct Shared {
+    collector: Collector,
+    tls: Option<Tls>,
+    auth: Option<Auth>,
+    ip_filter: IpFilter,
+    /// Closed when shutting down. Nothing is ever sent.
+    stopping: Receiver<()>,
+    /// Held by every connection, so shutdown can wait until all senders are dropped.
+    active: Sender<()>,
+}
 
-        let collector = collector.clone();
+impl Shared {
+    /// Serve `stream` in the background.
+    fn spawn<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let collector = self.collector.clone();
+        let tls = self.tls.clone();
+        let auth = self.auth.clone();
+        let stopping = self.stopping.clone();
+        let active = self.active.clone();
         smol::spawn(async move {
-            if let Err(err) = handle_client(collector, stream).await {
+            if let Err(err) = handle_connection(collector, tls, auth, stream, stopping).await {
                 tracing::error!(err = ?err, "error: serving request: {err}");
             }
+            drop(active);
         })
         .detach();
     }
+
+    /// Wait until shutting down.
+    async fn stopped(&self) {
+        self.stopping.recv().await.ok();
+    }
+}
+
+/// A socket accepting connections.
+enum Listener {
+    Tcp(smol::net::TcpListener),
+    /// Unix domain socket, with its path if litemon bound it, so it is removed on shutdown.
+    Unix(smol::net::unix::UnixListener, Option<PathBuf>),
+}
+
+impl Listener {
+    /// Bind to `addr`, applying the permissions in `socket` to Unix domain sockets.
+    async fn bind(addr: &ListenAddr, socket: &SocketConfig) -> anyhow::Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => Ok(Self::Tcp(bind_tcp(*addr)?.try_into()?)),
+            ListenAddr::Unix(path) => {
+                // Bind next to the final path and only move the socket there once its
+                // permissions are applied, so it is never reachable with the default ones.
+                let name = path.file_name().context("socket path has no file name")?;
+                let tmp = path.with_file_name(format!(
+                    ".{}.{}",
+                    name.to_string_lossy(),
+                    std::process::id()
+                ));
+                remove_stale_socket(&tmp).await?;
+                let listener = smol::net::unix::UnixListener::bind(&tmp)
+                    .with_context(|| format!("bind to {}", tmp.display()))?;
+                let moved = async {
+                    set_socket_permissions(&tmp, socket).await?;
+                    // Replaces a socket left behind by a previous run.
+                    remove_stale_socket(path).await?;
+                    smol::fs::rename(&tmp, path)
+                        .await
+                        .with_context(|| format!("moving socket to {}", path.display()))
+                };
+                if let Err(err) = moved.await {
+                    smol::fs::remove_file(&tmp).await.ok();
+                    return Err(err);
+                }
+
+                Ok(Self::Unix(listener, Some(path.clone())))
+            }
+        }
+    }
+
+    /// Adopt a socket passed by systemd.
+    fn from_systemd(socket: ListenSocket) -> anyhow::Result<Self> {
+        match socket {
+            ListenSocket::Tcp(listener) => Ok(Self::Tcp(listener.try_into()?)),
+            ListenSocket::Unix(listener) => Ok(Self::Unix(listener.try_into()?, None)),
+        }
+    }
+
+    /// Address of the socket for logging, e.g., `https://127.0.0.1:9774`.
+    fn describe(&self, tls: bool) -> String {
+        match self {
+            Self::Tcp(listener) => {
+                let scheme = if tls { "https" } else { "http" };
+                let addr = listener.local_addr().map(|el| el.to_string());
+                format!("{scheme}://{}", addr.unwrap_or_default())
+            }
+            Self::Unix(_, Some(path)) => format!("unix:{}", path.display()),
+            Self::Unix(listener, None) => {
+                let path = listener.local_addr().ok();
+                let path = path.as_ref().and_then(|el| el.as_pathname());
+                format!("unix:{}", path.unwrap_or_else(|| Path::new("")).display())
+            }
+        }
+    }
+
+    /// Path of the Unix domain socket, if litemon bound it.
+    fn bound_path(&self) -> Option<&Path> {
+        match self {
+            Self::Unix(_, path) => path.as_deref(),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Accept connections until shutting down.
+    ///
+    /// Failing to accept a connection is logged and retried after [`ACCEPT_BACKOFF`], unless
+    /// the socket itself is unusable.
+    async fn serve(self, ctx: Shared) -> anyhow::Result<()> {
+        loop {
+            let accepted = smol::future::or(async { Some(self.accept(&ctx).await) }, async {
+                ctx.stopped().await;
+                None
+            });
+            let Some(accepted) = accepted.await else {
+                return Ok(());
+            };
+            let Err(err) = accepted else {
+                continue;
+            };
+            let errno = err.raw_os_error().map(Errno::from_raw);
+            if matches!(
+                errno,
+                Some(Errno::EBADF | Errno::EINVAL | Errno::ENOTSOCK | Errno::EOPNOTSUPP)
+            ) {
+                return Err(err).context("accepting connections");
+            }
+
+            tracing::warn!("accepting connection failed: {err}");
+            smol::future::or(
+                async {
+                    smol::Timer::after(ACCEPT_BACKOFF).await;
+                },
+                ctx.stopped(),
+            )
+            .await;
+        }
+    }
+
+    /// Accept a single connection and serve it in the background. Connections via TCP from peers
+    /// rejected by the IP filter are dropped right away.
+    async fn accept(&self, ctx: &Shared) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                if !ctx.ip_filter.is_allowed(peer.ip()) {
+                    tracing::warn!(%peer, "rejected connection from disallowed address");
+                    return Ok(());
+                }
+                ctx.spawn(stream);
+            }
+            Self::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                ctx.spawn(stream);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sockets to serve the metrics on, see [`bind`].
+pub struct Listeners(Vec<Listener>);
+
+/// Take the sockets passed by systemd if litemon was socket-activated. Otherwise, bind to the
+/// addresses configured in `server`.
+pub async fn bind(server: &ServerConfig) -> anyhow::Result<Listeners> {
+    let activated = systemd::listen_fds().context("adopting sockets from systemd")?;
+    if !activated.is_empty() {
+        tracing::info!("using {} socket(s) passed by systemd", activated.len());
+        let listeners = activated
+            .into_iter()
+            .map(Listener::from_systemd)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        return Ok(Listeners(listeners));
+    }
+
+    let mut listeners = Vec::new();
+    for addr in server.listen_addrs()? {
+        match Listener::bind(&addr, &server.socket).await {
+            Ok(listener) => listeners.push(listener),
+            Err(err) => {
+                // Don't leave the sockets bound so far behind, nothing will serve them.
+                let paths = listeners
+                    .iter()
+                    .filter_map(Listener::bound_path)
+                    .map(Path::to_path_buf)
+                    .collect::<Vec<_>>();
+                drop(listeners);
+                remove_sockets(&paths).await;
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(Listeners(listeners))
+}
+
+/// Serves the metrics endpoint on `listeners`, over TLS if `tls` is set and requiring
+/// credentials if `auth` is set. Connections via TCP from peers rejected by `ip_filter` are
+/// dropped right away.
+///
+/// Once `shutdown` completes, stops accepting connections and waits up to `grace_period` for
+/// requests in progress to finish.
+pub async fn serve<F>(
+    listeners: Listeners,
+    collector: Collector,
+    tls: Option<Tls>,
+    auth: Option<Auth>,
+    ip_filter: IpFilter,
+    shutdown: F,
+    grace_period: Duration,
+) -> anyhow::Result<()>
+where
+    F: Future<Output = ()>,
+{
+    let (stop, stopping) = smol::channel::bounded(1);
+    let (active, idle) = smol::channel::bounded::<()>(1);
+    let ctx = Shared {
+        collector,
+        tls,
+        auth,
+        ip_filter,
+        stopping,
+        active,
+    };
+    // Sockets passed by systemd are left to it.
+    let paths = listeners
+        .0
+        .iter()
+        .filter_map(Listener::bound_path)
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    // Serve each socket in its own task. A socket that fails is logged, the others keep
+    // serving.
+    let (tx, rx) = smol::channel::unbounded();
+    let count = listeners.0.len();
+    for listener in listeners.0 {
+        let addr = listener.describe(ctx.tls.is_some());
+        tracing::info!("listening on {addr}");
+        let ctx = ctx.clone();
+        let tx = tx.clone();
+        smol::spawn(async move {
+            if let Err(err) = listener.serve(ctx).await {
+                tracing::error!("stopped listening on {addr}: {err:#}");
+            }
+            tx.send(()).await.ok();
+        })
+        .detach();
+    }
+    drop(ctx);
+
+    // Listeners only stop on their own if they failed.
+    let all_failed = async {
+        for _ in 0..count {
+            rx.recv().await.ok();
+        }
+        true
+    };
+    let failed = smol::future::or(all_failed, async {
+        shutdown.await;
+        false
+    })
+    .await;
+    if failed {
+        remove_sockets(&paths).await;
+        anyhow::bail!("all {count} listener(s) failed");
+    }
+
+    tracing::info!(
+        "shutting down, waiting up to {}ms for open connections",
+        grace_period.as_millis()
+    );
+    stop.close();
+    remove_sockets(&paths).await;
+    let finished = smol::future::or(
+        async {
+            idle.recv().await.ok();
+            true
+        },
+        async {
+            smol::Timer::after(grace_period).await;
+            false
+        },
+    )
+    .await;
+    if !finished {
+        tracing::warn!(
+            "closing {} connection(s) still open after the grace period",
+            idle.sender_count()
+        );
+    }
+
+    Ok(())
+}
+
+/// Create a TCP listener on `addr`.
+///
+/// Listening on `::` also accepts IPv4 connections, regardless of the system default.
+fn bind_tcp(addr: SocketAddr) -> anyhow::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&addr.into())
+        .with_context(|| format!("bind to {addr}"))?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+/// Remove the socket at `path` if it exists, e.g., left behind by a previous run, as it would make
+/// binding fail.
+async fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    if let Ok(metadata) = smol::fs::symlink_metadata(path).await {
+        anyhow::ensure!(
+            metadata.file_type().is_socket(),
+            "{} exists and is not a socket",
+            path.display()
+        );
+        smol::fs::remove_file(path)
+            .await
+            .with_context(|| format!("removing stale socket {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Remove the Unix domain sockets at `paths` after they stopped accepting connections.
+async fn remove_sockets(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(err) = smol::fs::remove_file(path).await {
+            tracing::warn!("removing socket {} failed: {err}", path.display());
+        }
+    }
+}
+
+/// Apply the mode and ownership from `socket` to the socket at `path`.
+async fn set_socket_permissions(path: &Path, socket: &SocketConfig) -> anyhow::Result<()> {
+    if let Some(mode) = socket.mode {
+        smol::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .await
+            .with_context(|| format!("setting mode of {}", path.display()))?;
+    }
+
+    let owner = socket.owner.as_deref().map(resolve_user).transpose()?;
+    let group = socket.group.as_deref().map(resolve_group).transpose()?;
+    if owner.is_some() || group.is_some() {
+        nix::unistd::chown(path, owner, group)
+            .with_context(|| format!("changing owner of {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Look up the user with the name or numeric ID `name`.
+fn resolve_user(name: &str) -> anyhow::Result<Uid> {
+    if let Ok(uid) = name.parse() {
+        return Ok(Uid::from_raw(uid));
+    }
+
+    User::from_name(name)
+        .with_context(|| format!("looking up user {name}"))?
+        .map(|el| el.uid)
+        .with_context(|| format!("no such user: {name}"))
+}
+
+/// Look up the group with the name or numeric ID `name`.
+fn resolve_group(name: &str) -> anyhow::Result<Gid> {
+    if let Ok(gid) = name.parse() {
+        return Ok(Gid::from_raw(gid));
+    }
+
+    Group::from_name(name)
+        .with_context(|| format!("looking up group {name}"))?
+        .map(|el| el.gid)
+        .with_context(|| format!("no such group: {name}"))
 }
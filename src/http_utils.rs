@@ -1,10 +1,11 @@
 //! Various utilities for the HTTP serve.
 use std::convert::Infallible;
 
+use http::{HeaderValue, StatusCode, header};
 use http_body_util::combinators::BoxBody;
-use http_body_util::{BodyExt, Empty};
-use hyper::body::Bytes;
+use http_body_util::{BodyExt, Empty, Full};
 use hyper::Response;
+use hyper::body::Bytes;
 
 /// Create a HTTP `404 Not Found` response.
 pub(crate) fn not_found() -> Response<BoxBody<Bytes, Infallible>> {
@@ -18,6 +19,46 @@ pub(crate) fn not_found() -> Response<BoxBody<Bytes, Infallible>> {
     res
 }
 
+/// Create a HTTP response with `status` and the plain text `body`.
+pub(crate) fn text(status: StatusCode, body: String) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut res = Response::new(Full::new(Bytes::from(body)).boxed());
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+
+    res
+}
+
+/// Create a HTTP `200 OK` response with the HTML `body`.
+pub(crate) fn html(body: String) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut res = Response::new(Full::new(Bytes::from(body)).boxed());
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+
+    res
+}
+
+/// Create a HTTP `400 Bad Request` response, explaining the problem in `message`.
+pub(crate) fn bad_request(message: String) -> Response<BoxBody<Bytes, Infallible>> {
+    text(StatusCode::BAD_REQUEST, message)
+}
+
+/// Create a HTTP `406 Not Acceptable` response.
+pub(crate) fn not_acceptable() -> Response<BoxBody<Bytes, Infallible>> {
+    let mut res = Response::new(
+        Empty::<Bytes>::new()
+            .map_err(|never| match never {})
+            .boxed(),
+    );
+    *res.status_mut() = hyper::StatusCode::NOT_ACCEPTABLE;
+
+    res
+}
+
 /// Create a HTTP `500 Internal Server Error` response.
 pub(crate) fn internal_server_error() -> Response<BoxBody<Bytes, Infallible>> {
     let mut res = Response::new(
@@ -29,3 +70,62 @@ pub(crate) fn internal_server_error() -> Response<BoxBody<Bytes, Infallible>> {
 
     res
 }
+
+/// Create a HTTP `401 Unauthorized` response, asking for credentials with `challenges`.
+pub(crate) fn unauthorized(challenges: Vec<HeaderValue>) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut res = Response::new(
+        Empty::<Bytes>::new()
+            .map_err(|never| match never {})
+            .boxed(),
+    );
+    *res.status_mut() = hyper::StatusCode::UNAUTHORIZED;
+    for challenge in challenges {
+        res.headers_mut()
+            .append(header::WWW_AUTHENTICATE, challenge);
+    }
+
+    res
+}
+
+/// Split the query string of a URL into its decoded keys and values.
+///
+/// Parameters without `=` are skipped. Invalid percent-encodings are kept as they are.
+///
+/// Public so the integration tests can cover the decoding; the server uses it for `collect[]`.
+pub fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter_map(|el| el.split_once('='))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect()
+}
+
+/// Decode `%XX` escapes and `+` (as space) in a component of a query string.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while let Some(&byte) = bytes.get(i) {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|el| byte == b'%' && el.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|el| std::str::from_utf8(el).ok())
+            .and_then(|el| u8::from_str_radix(el, 16).ok());
+        match (byte, escaped) {
+            (_, Some(decoded)) => {
+                ret.push(decoded);
+                i += 3;
+            }
+            (b'+', None) => {
+                ret.push(b' ');
+                i += 1;
+            }
+            (_, None) => {
+                ret.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&ret).into_owned()
+}
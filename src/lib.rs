@@ -1,8 +1,16 @@
 //! LiteMon metrics exporter.
 
+pub mod acl;
 pub mod args;
+pub mod auth;
 pub mod collector;
+pub mod compression;
 pub mod config;
+pub mod format;
 pub mod http;
 pub mod http_utils;
 pub mod metrics;
+pub mod protobuf;
+pub mod reload;
+pub mod systemd;
+pub mod tls;
@@ -1,10 +1,17 @@
 //! LiteMon. Lightweight prometheus metrics for Linux.
+use std::process::ExitCode;
 use std::rc::Rc;
 
+use async_signal::{Signal, Signals};
+use litemon::acl::IpFilter;
 use litemon::args::CliArgs;
+use litemon::auth::Auth;
 use litemon::collector::Collector;
-use litemon::config::UserConfig;
-use litemon::http;
+use litemon::config::{LogConfig, LogFormat, UserConfig};
+use litemon::reload::Reloader;
+use litemon::tls::Tls;
+use litemon::{http, systemd};
+use smol::stream::StreamExt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
@@ -13,28 +20,83 @@ use tracing_subscriber::util::SubscriberInitExt;
 static GLOBAL_ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 /// Synchronous entrypoint into the application.
-fn main() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{}=debug,zbus=info", env!("CARGO_CRATE_NAME")).into()),
-        )
-        .with(tracing_logfmt::builder().with_timestamp(false).layer())
-        .init();
+fn main() -> ExitCode {
+    let args = CliArgs::from_env().expect("invalid args");
+    if args.check_config {
+        return check_config(&args);
+    }
+
+    let mut config =
+        smol::block_on(UserConfig::from_path(&args.config_path)).expect("invalid config");
+    config.apply_args(&args);
+    init_logging(&config.log);
+    tracing::info!(
+        listen = config.server.listen.join(" "),
+        port = config.server.port,
+        log_level = config.log.level,
+        log_format = %config.log.format,
+        "loaded config from {}",
+        args.config_path.display()
+    );
 
     let ex = Rc::new(smol::LocalExecutor::new());
-    smol::block_on(ex.run(async {
-        async_main(&ex).await;
-    }));
+    smol::block_on(ex.run(async_main(&ex, args, config)))
+}
+
+/// Set up logging. `RUST_LOG` takes precedence over the level from the config.
+fn init_logging(config: &LogConfig) {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        config.level.as_ref().map_or_else(
+            || format!("{}=debug,zbus=info", env!("CARGO_CRATE_NAME")).into(),
+            tracing_subscriber::EnvFilter::new,
+        )
+    });
+
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.format {
+        LogFormat::Logfmt => registry
+            .with(tracing_logfmt::builder().with_timestamp(false).layer())
+            .init(),
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json())
+            .init(),
+    }
+}
+
+/// Validate the config file and print the effective configuration, including the overrides
+/// passed on the command line.
+fn check_config(args: &CliArgs) -> ExitCode {
+    match smol::block_on(UserConfig::from_path(&args.config_path)) {
+        Ok(mut config) => {
+            config.apply_args(args);
+            print!("{config}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err:#}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
 /// Real, asynchronous entrypoint.
 #[allow(clippy::future_not_send)]
-async fn async_main(_ex: &Rc<smol::LocalExecutor<'_>>) {
-    let args = CliArgs::from_env().expect("invalid args");
-    let config = UserConfig::from_path(&args.config_path)
-        .await
-        .expect("invalid config");
+async fn async_main(
+    ex: &Rc<smol::LocalExecutor<'_>>,
+    args: CliArgs,
+    config: UserConfig,
+) -> ExitCode {
+    // Register early, so signals received during startup still shut down gracefully.
+    let mut signals =
+        Signals::new([Signal::Term, Signal::Int]).expect("registering signal handlers failed");
+    let shutdown = async move {
+        if let Some(Ok(signal)) = signals.next().await {
+            tracing::info!("received {signal:?}");
+        }
+        if let Err(err) = systemd::notify("STOPPING=1") {
+            tracing::warn!("notifying systemd failed: {err:#}");
+        }
+    };
 
     let collector = Collector::new();
     collector
@@ -45,6 +107,29 @@ async fn async_main(_ex: &Rc<smol::LocalExecutor<'_>>) {
         .register()
         .await
         .expect("registering metrics failed");
+    collector.start().await;
+
+    let tls = match &config.server.tls {
+        Some(tls) => Some(Tls::new(tls).await.expect("loading TLS certificate failed")),
+        None => None,
+    };
+    let auth = match &config.server.auth {
+        Some(auth) => Some(Auth::new(auth).await.expect("loading bearer tokens failed")),
+        None => None,
+    };
+    let ip_filter = IpFilter::new(&config.server);
+
+    let watch_config = args.watch_config;
+    let reloader = Reloader::new(args, &config, collector.clone(), tls.clone(), auth.clone());
+    if watch_config {
+        ex.spawn(reloader.clone().on_change()).detach();
+    }
+    ex.spawn(async move {
+        if let Err(err) = reloader.on_sighup().await {
+            tracing::error!("error: reloading on SIGHUP: {err:#}");
+        }
+    })
+    .detach();
 
     // Figlet font: Standard
     // Alternatives: Sland, Big
@@ -55,7 +140,38 @@ async fn async_main(_ex: &Rc<smol::LocalExecutor<'_>>) {
     println!(r"|_____|_|\__\___|_|  |_|\___/|_| |_|");
     println!();
 
-    http::listen(collector.clone(), &args.listen_address, args.listen_port)
-        .await
-        .expect("starting http server");
+    let listeners = match http::bind(&config.server).await {
+        Ok(listeners) => listeners,
+        Err(err) => {
+            tracing::error!("error: starting http server: {err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = systemd::notify_ready(&collector.names().await) {
+        tracing::warn!("notifying systemd failed: {err:#}");
+    }
+    if let Some(interval) = systemd::watchdog_interval() {
+        ex.spawn(systemd::watchdog(interval, collector.clone()))
+            .detach();
+    }
+
+    let served = http::serve(
+        listeners,
+        collector.clone(),
+        tls,
+        auth,
+        ip_filter,
+        shutdown,
+        config.server.grace_period,
+    )
+    .await;
+
+    collector.shutdown().await;
+    if let Err(err) = served {
+        tracing::error!("error: serving http: {err:#}");
+        return ExitCode::FAILURE;
+    }
+    tracing::info!("shut down");
+
+    ExitCode::SUCCESS
 }
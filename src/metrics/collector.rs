@@ -1,32 +1,79 @@
 //! Collectors for all supported metrics.
 
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
+use std::sync::atomic::{AtomicU32, AtomicU64};
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use futures_concurrency::future::Join;
 use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
-use smol::lock::Mutex;
 
-use super::cpu::{CpuUsage, LoadAverages};
+use super::cpu::{CpuModeSeconds, CpuUsage, LoadAverages};
 use super::disk::IOMetrics;
 use super::fs::FilesystemUsage;
 use super::info::NodeInfo;
-use super::memory::MemoryStats;
+use super::memory::{MeminfoField, MemoryStats};
 use super::net::NetworkStats;
 use super::pressure::SystemPressure;
 use super::systemd_unit_state::{ActiveState, SystemdUnitState};
+use super::vmstat::{self, VmStat};
 use super::{DynFuture, Metric};
 
 /// Collector for memory stats.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct MemoryStatsCollector {
     gauge: Gauge<f64, AtomicU64>,
+    total_bytes: Gauge<u64, AtomicU64>,
+    free_bytes: Gauge<u64, AtomicU64>,
+    available_bytes: Gauge<u64, AtomicU64>,
+    used_bytes: Gauge<u64, AtomicU64>,
+    buffers_bytes: Gauge<u64, AtomicU64>,
+    cached_bytes: Gauge<u64, AtomicU64>,
+    swap_total_bytes: Gauge<u64, AtomicU64>,
+    swap_free_bytes: Gauge<u64, AtomicU64>,
+    swap_used_bytes: Gauge<u64, AtomicU64>,
+    /// Additionally configured `/proc/meminfo` fields.
+    fields: Vec<(MeminfoField, Gauge<u64, AtomicU64>)>,
+}
+
+impl MemoryStatsCollector {
+    pub fn new(options: &hashbrown::HashMap<String, String>) -> Result<Self> {
+        let fields = options
+            .get("fields")
+            .filter(|fields_str| !fields_str.is_empty())
+            .map_or_else(
+                || Ok(Vec::new()),
+                |fields_str| {
+                    fields_str
+                        .split(',')
+                        .map(|s| Ok((s.trim().parse()?, Gauge::default())))
+                        .collect::<Result<Vec<_>>>()
+                },
+            )?;
+
+        Ok(Self {
+            gauge: Gauge::default(),
+            total_bytes: Gauge::default(),
+            free_bytes: Gauge::default(),
+            available_bytes: Gauge::default(),
+            used_bytes: Gauge::default(),
+            buffers_bytes: Gauge::default(),
+            cached_bytes: Gauge::default(),
+            swap_total_bytes: Gauge::default(),
+            swap_free_bytes: Gauge::default(),
+            swap_used_bytes: Gauge::default(),
+            fields,
+        })
+    }
 }
 
 impl Metric for MemoryStatsCollector {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         let gauge_ref = &self.gauge;
         registry.register(
@@ -34,27 +81,229 @@ impl Metric for MemoryStatsCollector {
             "Memory used (0.0-1.0) in percent",
             gauge_ref.clone(),
         );
+        registry.register(
+            "litemon_memory_total_bytes",
+            "Total physical memory in bytes",
+            self.total_bytes.clone(),
+        );
+        registry.register(
+            "litemon_memory_free_bytes",
+            "Free memory in bytes",
+            self.free_bytes.clone(),
+        );
+        registry.register(
+            "litemon_memory_available_bytes",
+            "Memory available for starting new applications in bytes",
+            self.available_bytes.clone(),
+        );
+        registry.register(
+            "litemon_memory_used_bytes",
+            "Used memory in bytes",
+            self.used_bytes.clone(),
+        );
+        registry.register(
+            "litemon_memory_buffers_bytes",
+            "Memory used by kernel buffers in bytes",
+            self.buffers_bytes.clone(),
+        );
+        registry.register(
+            "litemon_memory_cached_bytes",
+            "Memory used by the page cache in bytes",
+            self.cached_bytes.clone(),
+        );
+        registry.register(
+            "litemon_swap_total_bytes",
+            "Total swap space in bytes",
+            self.swap_total_bytes.clone(),
+        );
+        registry.register(
+            "litemon_swap_free_bytes",
+            "Free swap space in bytes",
+            self.swap_free_bytes.clone(),
+        );
+        registry.register(
+            "litemon_swap_used_bytes",
+            "Used swap space in bytes",
+            self.swap_used_bytes.clone(),
+        );
+        for (field, gauge) in &self.fields {
+            registry.register(field.metric_name(), field.help(), gauge.clone());
+        }
     }
 
     fn collect(&self) -> DynFuture<'_, Result<()>> {
         Box::pin(async move {
             let stats = MemoryStats::current().await?;
             self.gauge.set(stats.used_percent);
+            self.total_bytes.set(stats.total_bytes);
+            self.free_bytes.set(stats.free_bytes);
+            self.available_bytes.set(stats.available_bytes);
+            self.used_bytes.set(stats.used_bytes);
+            self.buffers_bytes.set(stats.buffers_bytes);
+            self.cached_bytes.set(stats.cached_bytes);
+            self.swap_total_bytes.set(stats.swap_total_bytes);
+            self.swap_free_bytes.set(stats.swap_free_bytes);
+            self.swap_used_bytes.set(stats.swap_used_bytes);
+            for (field, gauge) in &self.fields {
+                if let Some(value) = field.value(&stats) {
+                    gauge.set(value);
+                }
+            }
             Ok(())
         })
     }
 }
 
-/// Collector for CPU metrics (load averages and CPU usage).
+/// Collector for virtual memory event counters and current values.
 #[derive(Debug)]
-pub struct CpuStatsCollector {
+pub struct VmStatCollector {
+    /// Counter or gauge for each configured `/proc/vmstat` field.
+    fields: Vec<(String, VmStatValue)>,
+}
+
+/// Metric a `/proc/vmstat` field is exported as, see [`vmstat::is_gauge`].
+#[derive(Debug)]
+enum VmStatValue {
+    Counter(Counter<u64, AtomicU64>),
+    Gauge(Gauge),
+}
+
+impl VmStatCollector {
+    pub fn new(options: &hashbrown::HashMap<String, String>) -> Result<Self> {
+        let fields = options
+            .get("fields")
+            .filter(|fields_str| !fields_str.is_empty())
+            .map_or_else(
+                || {
+                    vmstat::DEFAULT_FIELDS
+                        .iter()
+                        .map(|s| (*s).to_string())
+                        .collect()
+                },
+                |fields_str| {
+                    fields_str
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .collect::<Vec<_>>()
+                },
+            );
+
+        let mut ret: Vec<(String, VmStatValue)> = Vec::with_capacity(fields.len());
+        for field in fields {
+            vmstat::validate_field(&field)?;
+            anyhow::ensure!(
+                !ret.iter().any(|(el, _)| *el == field),
+                "duplicate vmstat field `{field}`"
+            );
+            let value = if vmstat::is_gauge(&field) {
+                VmStatValue::Gauge(Gauge::default())
+            } else {
+                VmStatValue::Counter(Counter::default())
+            };
+            ret.push((field, value));
+        }
+
+        Ok(Self { fields: ret })
+    }
+}
+
+impl Metric for VmStatCollector {
+    fn name(&self) -> &'static str {
+        "vmstat"
+    }
+
+    fn register(&self, registry: &mut prometheus_client::registry::Registry) {
+        for (field, value) in &self.fields {
+            let name = format!("litemon_vmstat_{field}");
+            match value {
+                VmStatValue::Counter(counter) => registry.register(
+                    name,
+                    format!("Number of {field} events from /proc/vmstat"),
+                    counter.clone(),
+                ),
+                VmStatValue::Gauge(gauge) => registry.register(
+                    name,
+                    format!("Current value of {field} from /proc/vmstat"),
+                    gauge.clone(),
+                ),
+            }
+        }
+    }
+
+    fn collect(&self) -> DynFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let stats = VmStat::current().await?;
+            for (field, value) in &self.fields {
+                let Some(current) = stats.counters.get(field) else {
+                    tracing::debug!("vmstat field {field} not reported by kernel");
+                    continue;
+                };
+
+                match value {
+                    VmStatValue::Counter(counter) => {
+                        let prev = counter.get();
+                        counter.inc_by(current.saturating_sub(prev));
+                    }
+                    VmStatValue::Gauge(gauge) => {
+                        gauge.set(i64::try_from(*current).unwrap_or(i64::MAX));
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Collector for load averages.
+#[derive(Debug, Default)]
+pub struct LoadAvgCollector {
     load_avg_1m: Gauge<f64, AtomicU64>,
     load_avg_5m: Gauge<f64, AtomicU64>,
     load_avg_15m: Gauge<f64, AtomicU64>,
+}
+
+impl Metric for LoadAvgCollector {
+    fn name(&self) -> &'static str {
+        "loadavg"
+    }
+
+    fn register(&self, registry: &mut prometheus_client::registry::Registry) {
+        registry.register(
+            "litemon_load_avg_1m",
+            "Load average over 1 minute",
+            self.load_avg_1m.clone(),
+        );
+        registry.register(
+            "litemon_load_avg_5m",
+            "Load average over 5 minutes",
+            self.load_avg_5m.clone(),
+        );
+        registry.register(
+            "litemon_load_avg_15m",
+            "Load average over 15 minutes",
+            self.load_avg_15m.clone(),
+        );
+    }
+
+    fn collect(&self) -> DynFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let load_avg = LoadAverages::current().await?;
+            self.load_avg_1m.set(f64::from(load_avg.one));
+            self.load_avg_5m.set(f64::from(load_avg.five));
+            self.load_avg_15m.set(f64::from(load_avg.fifteen));
+            Ok(())
+        })
+    }
+}
+
+/// Collector for CPU usage, averaged over a sampling period, and the time spent in each mode.
+#[derive(Debug)]
+pub struct CpuUsageCollector {
     cpu_usage_overall: Gauge<f64, AtomicU64>,
     cpu_usage_per_core: Family<CpuCoreLabels, Gauge<f64, AtomicU64>>,
-    last_cpu_snapshot: Mutex<CpuUsage>,
-    load_avg_enabled: AtomicBool,
+    cpu_seconds: Family<CpuModeLabels, Counter<f64, AtomicU64>>,
+    period: Duration,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -62,50 +311,36 @@ struct CpuCoreLabels {
     core: String,
 }
 
-impl CpuStatsCollector {
-    pub async fn new(options: hashbrown::HashMap<String, String>) -> Result<Self> {
-        let initial_snapshot = CpuUsage::now().await?;
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CpuModeLabels {
+    /// CPU core index.
+    cpu: String,
+    /// CPU mode (e.g., `user`, `iowait`, `steal`).
+    mode: String,
+}
+
+impl CpuUsageCollector {
+    pub fn new(options: &hashbrown::HashMap<String, String>) -> Result<Self> {
+        let period_ms = options
+            .get("period_ms")
+            .map_or(Ok(200), |period| period.parse::<u64>())
+            .context("parsing period_ms")?;
 
-        let ret = Self {
-            load_avg_1m: Gauge::default(),
-            load_avg_5m: Gauge::default(),
-            load_avg_15m: Gauge::default(),
+        Ok(Self {
             cpu_usage_overall: Gauge::default(),
             cpu_usage_per_core: Family::default(),
-            last_cpu_snapshot: Mutex::new(initial_snapshot),
-            load_avg_enabled: AtomicBool::new(false),
-        };
-        ret.load_avg_enabled.store(
-            options.get("load_avg_enabled").is_some_and(|b| b == "true"),
-            std::sync::atomic::Ordering::SeqCst,
-        );
-
-        Ok(ret)
+            cpu_seconds: Family::default(),
+            period: Duration::from_millis(period_ms),
+        })
     }
 }
 
-impl Metric for CpuStatsCollector {
+impl Metric for CpuUsageCollector {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
-        if self
-            .load_avg_enabled
-            .load(std::sync::atomic::Ordering::Acquire)
-        {
-            registry.register(
-                "litemon_load_avg_1m",
-                "Load average over 1 minute",
-                self.load_avg_1m.clone(),
-            );
-            registry.register(
-                "litemon_load_avg_5m",
-                "Load average over 5 minutes",
-                self.load_avg_5m.clone(),
-            );
-            registry.register(
-                "litemon_load_avg_15m",
-                "Load average over 15 minutes",
-                self.load_avg_15m.clone(),
-            );
-        }
         registry.register(
             "litemon_cpu_usage_overall",
             "Overall CPU usage percentage (0.0-1.0)",
@@ -116,34 +351,40 @@ impl Metric for CpuStatsCollector {
             "Per-core CPU usage percentage (0.0-1.0)",
             self.cpu_usage_per_core.clone(),
         );
+        registry.register(
+            "litemon_cpu_seconds",
+            "Seconds the CPU cores spent in each mode",
+            self.cpu_seconds.clone(),
+        );
     }
 
     fn collect(&self) -> DynFuture<'_, Result<()>> {
         Box::pin(async move {
-            let load_avg = LoadAverages::current().await?;
-            if self
-                .load_avg_enabled
-                .load(std::sync::atomic::Ordering::Acquire)
-            {
-                self.load_avg_1m.set(f64::from(load_avg.one));
-                self.load_avg_5m.set(f64::from(load_avg.five));
-                self.load_avg_15m.set(f64::from(load_avg.fifteen));
-            }
-
-            let current_snapshot = CpuUsage::now().await?;
-            let mut prev_snapshot = self.last_cpu_snapshot.lock().await;
-            let overall_usage = current_snapshot.percentage_all_cores(&prev_snapshot);
-            self.cpu_usage_overall.set(overall_usage);
+            let usage = CpuUsage::period(self.period).await?;
+            self.cpu_usage_overall.set(usage.overall);
 
-            let per_core_usage = current_snapshot.percentage_per_core(&prev_snapshot);
-            for (core_idx, usage) in per_core_usage.iter().enumerate() {
+            for (core_idx, core_usage) in usage.per_core.iter().enumerate() {
                 let labels = CpuCoreLabels {
                     core: core_idx.to_string(),
                 };
-                self.cpu_usage_per_core.get_or_create(&labels).set(*usage);
+                self.cpu_usage_per_core
+                    .get_or_create(&labels)
+                    .set(*core_usage);
             }
 
-            *prev_snapshot = current_snapshot;
+            let per_core_seconds = CpuModeSeconds::per_core().await?;
+            for (core_idx, seconds) in per_core_seconds.iter().enumerate() {
+                for (mode, secs) in seconds.modes() {
+                    let labels = CpuModeLabels {
+                        cpu: core_idx.to_string(),
+                        mode: mode.to_owned(),
+                    };
+                    let counter = self.cpu_seconds.get_or_create(&labels);
+                    // `iowait` is known to occasionally go backwards, counters must not.
+                    let secs_prev = counter.get();
+                    counter.inc_by((secs - secs_prev).max(0.0));
+                }
+            }
 
             Ok(())
         })
@@ -184,6 +425,10 @@ impl FilesystemStatsCollector {
 }
 
 impl Metric for FilesystemStatsCollector {
+    fn name(&self) -> &'static str {
+        "filesystem"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_fs_usage_ratio",
@@ -194,9 +439,9 @@ impl Metric for FilesystemStatsCollector {
 
     fn collect(&self) -> DynFuture<'_, Result<()>> {
         Box::pin(async move {
-            let mountpoints = &self.mountpoints;
+            let mut failed = Vec::new();
 
-            for mountpoint in mountpoints {
+            for mountpoint in &self.mountpoints {
                 match FilesystemUsage::new(&mountpoint).await {
                     Ok(usage) => {
                         let labels = FilesystemLabels {
@@ -209,13 +454,23 @@ impl Metric for FilesystemStatsCollector {
                             .set(usage.usage_ratio);
                     }
                     Err(e) => {
-                        return Err(anyhow::anyhow!(
-                            "Failed to collect filesystem stats for {mountpoint}: {e}",
-                        ));
+                        // keep updating the remaining mountpoints, one broken
+                        // mount must not freeze the others' values.
+                        tracing::warn!(
+                            "Failed to collect filesystem stats for {mountpoint}: {e:#}"
+                        );
+                        failed.push(mountpoint.as_str());
                     }
                 }
             }
 
+            if !failed.is_empty() {
+                anyhow::bail!(
+                    "Failed to collect filesystem stats for {}",
+                    failed.join(", ")
+                );
+            }
+
             Ok(())
         })
     }
@@ -259,6 +514,10 @@ impl NetworkStatsCollector {
 }
 
 impl Metric for NetworkStatsCollector {
+    fn name(&self) -> &'static str {
+        "network"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_net_bytes_received",
@@ -353,6 +612,10 @@ impl SystemdUnitStateCollector {
 }
 
 impl Metric for SystemdUnitStateCollector {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_systemd_unit_state",
@@ -392,6 +655,10 @@ impl Metric for SystemdUnitStateCollector {
             Ok(())
         })
     }
+
+    fn shutdown(&self) -> DynFuture<'_, Result<()>> {
+        Box::pin(self.systemd_client.close())
+    }
 }
 
 #[derive(Debug)]
@@ -423,6 +690,10 @@ impl NodeInfoCollector {
 }
 
 impl Metric for NodeInfoCollector {
+    fn name(&self) -> &'static str {
+        "info"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_node_info",
@@ -444,6 +715,10 @@ pub struct PressureCollector {
 }
 
 impl Metric for PressureCollector {
+    fn name(&self) -> &'static str {
+        "pressure"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         let io_total = &self.io_total;
         registry.register(
@@ -522,6 +797,10 @@ impl DiskStatsCollector {
 }
 
 impl Metric for DiskStatsCollector {
+    fn name(&self) -> &'static str {
+        "diskstats"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_disk_bytes_written_total",
@@ -570,6 +849,10 @@ pub struct NodeUptimeCollector {
 }
 
 impl Metric for NodeUptimeCollector {
+    fn name(&self) -> &'static str {
+        "uptime"
+    }
+
     fn register(&self, registry: &mut prometheus_client::registry::Registry) {
         registry.register(
             "litemon_node_uptime",
@@ -24,7 +24,8 @@ impl LoadAverages {
         let load = smol::unblock(|| {
             let ret = procfs::LoadAverage::current().context("reading /proc/loadavg")?;
             Ok::<procfs::LoadAverage, anyhow::Error>(ret)
-        }).await?;
+        })
+        .await?;
 
         Ok(Self {
             one: load.one,
@@ -43,6 +44,84 @@ pub struct CpuUsage {
     pub per_core_ticks: Vec<CpuTime>,
 }
 
+/// CPU usage (0.0-1.0) averaged over a period, see [`CpuUsage::period()`].
+#[derive(Debug, Clone)]
+pub struct CpuUsagePercentage {
+    /// Usage of all CPU cores combined.
+    pub overall: f64,
+    /// Usage of each CPU core.
+    pub per_core: Vec<f64>,
+}
+
+/// Seconds a single CPU core spent in each mode since boot.
+///
+/// Modes which are not reported by the running kernel are `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuModeSeconds {
+    pub user: f64,
+    pub nice: f64,
+    pub system: f64,
+    pub idle: f64,
+    pub iowait: Option<f64>,
+    pub irq: Option<f64>,
+    pub softirq: Option<f64>,
+    pub steal: Option<f64>,
+    pub guest: Option<f64>,
+    pub guest_nice: Option<f64>,
+}
+
+impl CpuModeSeconds {
+    /// Retrieve the seconds spent in each mode for each CPU core from `/proc/stat`.
+    #[allow(clippy::cast_precision_loss)]
+    pub async fn per_core() -> Result<Vec<Self>> {
+        let stat = smol::unblock(|| {
+            let ret = procfs::KernelStats::current().context("reading /proc/stat")?;
+            Ok::<procfs::KernelStats, anyhow::Error>(ret)
+        })
+        .await?;
+        let tps = procfs::ticks_per_second() as f64;
+        let secs = |ticks: u64| ticks as f64 / tps;
+
+        let ret = stat
+            .cpu_time
+            .into_iter()
+            .map(|cputime| Self {
+                user: secs(cputime.user),
+                nice: secs(cputime.nice),
+                system: secs(cputime.system),
+                idle: secs(cputime.idle),
+                iowait: cputime.iowait.map(secs),
+                irq: cputime.irq.map(secs),
+                softirq: cputime.softirq.map(secs),
+                steal: cputime.steal.map(secs),
+                guest: cputime.guest.map(secs),
+                guest_nice: cputime.guest_nice.map(secs),
+            })
+            .collect();
+
+        Ok(ret)
+    }
+
+    /// Returns all modes reported by the kernel as `(mode, seconds)` pairs.
+    pub fn modes(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("user", Some(self.user)),
+            ("nice", Some(self.nice)),
+            ("system", Some(self.system)),
+            ("idle", Some(self.idle)),
+            ("iowait", self.iowait),
+            ("irq", self.irq),
+            ("softirq", self.softirq),
+            ("steal", self.steal),
+            ("guest", self.guest),
+            ("guest_nice", self.guest_nice),
+        ]
+        .into_iter()
+        .filter_map(|(mode, secs)| Some((mode, secs?)))
+        .collect()
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct CpuTime {
     /// Ticks spent idling.
@@ -135,12 +214,15 @@ impl CpuUsage {
             .collect::<Vec<_>>()
     }
 
-    /// Calculate the CPU usage between two snapshots separated by `period` for each CPU core.
-    pub async fn period(period: Duration) -> Result<Vec<f64>> {
+    /// Calculate the CPU usage between two snapshots separated by `period`.
+    pub async fn period(period: Duration) -> Result<CpuUsagePercentage> {
         let stat1 = Self::now().await.context("creating first snapshot")?;
         smol::Timer::after(period).await;
         let stat2 = Self::now().await.context("creating second snapshot")?;
 
-        Ok(stat2.percentage_per_core(&stat1))
+        Ok(CpuUsagePercentage {
+            overall: stat2.percentage_all_cores(&stat1),
+            per_core: stat2.percentage_per_core(&stat1),
+        })
     }
 }
@@ -1,5 +1,7 @@
 //! Memory metrics collection.
 
+use std::str::FromStr;
+
 use anyhow::{Context, Result};
 use procfs::Current;
 
@@ -33,30 +35,48 @@ pub async fn get_memory_used_percentage() -> Result<f64> {
 }
 
 /// Detailed memory statistics.
+///
+/// All sizes are in bytes (`procfs` converts the kibibytes reported by `/proc/meminfo`).
 #[derive(Debug)]
 pub struct MemoryStats {
-    /// Total physical memory in kilobytes
-    pub total_kb: u64,
-    /// Free memory in kilobytes
-    pub free_kb: u64,
-    /// Available memory in kilobytes (estimate of how much memory is available for starting new applications)
-    pub available_kb: u64,
-    /// Used memory in kilobytes
-    pub used_kb: u64,
+    /// Total physical memory in bytes
+    pub total_bytes: u64,
+    /// Free memory in bytes
+    pub free_bytes: u64,
+    /// Available memory in bytes (estimate of how much memory is available for starting new applications)
+    pub available_bytes: u64,
+    /// Used memory in bytes
+    pub used_bytes: u64,
     /// Percentage of memory used (0.0 to 1.0)
     pub used_percent: f64,
-    /// Buffers memory in kilobytes
-    pub buffers_kb: u64,
-    /// Cached memory in kilobytes
-    pub cached_kb: u64,
-    /// Swap total in kilobytes
-    pub swap_total_kb: u64,
-    /// Swap free in kilobytes
-    pub swap_free_kb: u64,
-    /// Swap used in kilobytes
-    pub swap_used_kb: u64,
+    /// Buffers memory in bytes
+    pub buffers_bytes: u64,
+    /// Cached memory in bytes
+    pub cached_bytes: u64,
+    /// Swap total in bytes
+    pub swap_total_bytes: u64,
+    /// Swap free in bytes
+    pub swap_free_bytes: u64,
+    /// Swap used in bytes
+    pub swap_used_bytes: u64,
     /// Percentage of swap used (0.0 to 1.0)
     pub swap_used_percent: f64,
+    /// Memory waiting to be written back to disk in bytes
+    pub dirty_bytes: u64,
+    /// Memory actively being written back to disk in bytes
+    pub writeback_bytes: u64,
+    /// Shared memory (including tmpfs) in bytes
+    pub shmem_bytes: Option<u64>,
+    /// Reclaimable slab memory in bytes
+    pub sreclaimable_bytes: Option<u64>,
+    /// Size of the huge page pool
+    pub hugepages_total: Option<u64>,
+    /// Number of huge pages not yet allocated
+    pub hugepages_free: Option<u64>,
+    /// Number of huge pages reserved, but not yet allocated
+    pub hugepages_rsvd: Option<u64>,
+    /// Number of surplus huge pages above the pool size
+    pub hugepages_surp: Option<u64>,
 }
 
 impl MemoryStats {
@@ -74,35 +94,118 @@ impl MemoryStats {
         })
         .await?;
 
-        let total_kb = meminfo.mem_total;
-        let free_kb = meminfo.mem_free;
-        let available_kb = meminfo
+        let total_bytes = meminfo.mem_total;
+        let free_bytes = meminfo.mem_free;
+        let available_bytes = meminfo
             .mem_available
-            .unwrap_or_else(|| free_kb + meminfo.buffers + meminfo.cached);
-        let used_kb = total_kb - available_kb;
-        let used_percent = used_kb as f64 / total_kb as f64;
-
-        let swap_total_kb = meminfo.swap_total;
-        let swap_free_kb = meminfo.swap_free;
-        let swap_used_kb = swap_total_kb - swap_free_kb;
-        let swap_used_percent = if swap_total_kb > 0 {
-            swap_used_kb as f64 / swap_total_kb as f64
+            .unwrap_or_else(|| free_bytes + meminfo.buffers + meminfo.cached);
+        let used_bytes = total_bytes - available_bytes;
+        let used_percent = used_bytes as f64 / total_bytes as f64;
+
+        let swap_total_bytes = meminfo.swap_total;
+        let swap_free_bytes = meminfo.swap_free;
+        let swap_used_bytes = swap_total_bytes - swap_free_bytes;
+        let swap_used_percent = if swap_total_bytes > 0 {
+            swap_used_bytes as f64 / swap_total_bytes as f64
         } else {
             0.0_f64
         };
 
         Ok(Self {
-            total_kb,
-            free_kb,
-            available_kb,
-            used_kb,
+            total_bytes,
+            free_bytes,
+            available_bytes,
+            used_bytes,
             used_percent,
-            buffers_kb: meminfo.buffers,
-            cached_kb: meminfo.cached,
-            swap_total_kb,
-            swap_free_kb,
-            swap_used_kb,
+            buffers_bytes: meminfo.buffers,
+            cached_bytes: meminfo.cached,
+            swap_total_bytes,
+            swap_free_bytes,
+            swap_used_bytes,
             swap_used_percent,
+            dirty_bytes: meminfo.dirty,
+            writeback_bytes: meminfo.writeback,
+            shmem_bytes: meminfo.shmem,
+            sreclaimable_bytes: meminfo.s_reclaimable,
+            hugepages_total: meminfo.hugepages_total,
+            hugepages_free: meminfo.hugepages_free,
+            hugepages_rsvd: meminfo.hugepages_rsvd,
+            hugepages_surp: meminfo.hugepages_surp,
         })
     }
 }
+
+/// Optional fields of `/proc/meminfo` which can be exported in addition to the default set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeminfoField {
+    Dirty,
+    Writeback,
+    Shmem,
+    SReclaimable,
+    HugePagesTotal,
+    HugePagesFree,
+    HugePagesRsvd,
+    HugePagesSurp,
+}
+
+impl MeminfoField {
+    /// Name of the metric this field is exported as.
+    pub fn metric_name(self) -> &'static str {
+        match self {
+            Self::Dirty => "litemon_memory_dirty_bytes",
+            Self::Writeback => "litemon_memory_writeback_bytes",
+            Self::Shmem => "litemon_memory_shmem_bytes",
+            Self::SReclaimable => "litemon_memory_sreclaimable_bytes",
+            Self::HugePagesTotal => "litemon_memory_hugepages_total_pages",
+            Self::HugePagesFree => "litemon_memory_hugepages_free_pages",
+            Self::HugePagesRsvd => "litemon_memory_hugepages_reserved_pages",
+            Self::HugePagesSurp => "litemon_memory_hugepages_surplus_pages",
+        }
+    }
+
+    /// Help text of the metric this field is exported as.
+    pub fn help(self) -> &'static str {
+        match self {
+            Self::Dirty => "Memory waiting to be written back to disk in bytes",
+            Self::Writeback => "Memory actively being written back to disk in bytes",
+            Self::Shmem => "Shared memory (including tmpfs) in bytes",
+            Self::SReclaimable => "Reclaimable slab memory in bytes",
+            Self::HugePagesTotal => "Size of the huge page pool",
+            Self::HugePagesFree => "Number of huge pages not yet allocated",
+            Self::HugePagesRsvd => "Number of huge pages reserved, but not yet allocated",
+            Self::HugePagesSurp => "Number of surplus huge pages above the pool size",
+        }
+    }
+
+    /// Value of this field in `stats`, or `None` if not reported by the running kernel.
+    pub fn value(self, stats: &MemoryStats) -> Option<u64> {
+        match self {
+            Self::Dirty => Some(stats.dirty_bytes),
+            Self::Writeback => Some(stats.writeback_bytes),
+            Self::Shmem => stats.shmem_bytes,
+            Self::SReclaimable => stats.sreclaimable_bytes,
+            Self::HugePagesTotal => stats.hugepages_total,
+            Self::HugePagesFree => stats.hugepages_free,
+            Self::HugePagesRsvd => stats.hugepages_rsvd,
+            Self::HugePagesSurp => stats.hugepages_surp,
+        }
+    }
+}
+
+impl FromStr for MeminfoField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Dirty" => Ok(Self::Dirty),
+            "Writeback" => Ok(Self::Writeback),
+            "Shmem" => Ok(Self::Shmem),
+            "SReclaimable" => Ok(Self::SReclaimable),
+            "HugePages_Total" => Ok(Self::HugePagesTotal),
+            "HugePages_Free" => Ok(Self::HugePagesFree),
+            "HugePages_Rsvd" => Ok(Self::HugePagesRsvd),
+            "HugePages_Surp" => Ok(Self::HugePagesSurp),
+            field => Err(anyhow::anyhow!("unsupported meminfo field: {field}")),
+        }
+    }
+}
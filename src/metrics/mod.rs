@@ -11,12 +11,19 @@ pub mod memory;
 pub mod net;
 pub mod pressure;
 pub mod systemd_unit_state;
+pub mod vmstat;
 
 /// A boxed future. Construct with `Box::pin(async move { ... })`.
 pub type DynFuture<'a, T> = std::pin::Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
 /// Trait that's to be implemented by any supported metric.
 pub trait Metric: Send + Sync + std::fmt::Debug {
+    /// Short, unique name of the collector (e.g., `cpu`). Used as the `collector` label.
+    fn name(&self) -> &'static str;
     fn register(&self, registry: &mut prometheus_client::registry::Registry);
     fn collect(&self) -> DynFuture<'_, Result<()>>;
+    /// Release resources held by the metric, e.g., connections. Called once on exit.
+    fn shutdown(&self) -> DynFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
 }
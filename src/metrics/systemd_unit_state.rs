@@ -112,6 +112,15 @@ impl SystemdUnitState<'_> {
         })
     }
 
+    /// Close the connection to the D-Bus. Later queries fail.
+    pub async fn close(&self) -> anyhow::Result<()> {
+        self.connection
+            .clone()
+            .close()
+            .await
+            .context("closing d-bus connection")
+    }
+
     /// Retrieve the active state of the specified unit. Unit name must have the suffix (e.g.,
     /// `.service`).
     pub async fn active_state(&self, unit: &str) -> anyhow::Result<ActiveState> {
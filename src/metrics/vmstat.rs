@@ -0,0 +1,59 @@
+//! Virtual memory statistics.
+
+use anyhow::{Context, Result};
+use hashbrown::HashMap;
+
+/// Fields of `/proc/vmstat` collected if none are configured explicitly.
+pub const DEFAULT_FIELDS: &[&str] = &[
+    "pgfault",
+    "pgmajfault",
+    "pswpin",
+    "pswpout",
+    "pgscan_kswapd",
+    "pgscan_direct",
+    "pgsteal_kswapd",
+    "pgsteal_direct",
+    "oom_kill",
+];
+
+/// Check that `field` can be used in a metric name, i.e., only consists of ASCII letters, digits
+/// and underscores, like the fields in `/proc/vmstat`.
+pub fn validate_field(field: &str) -> Result<()> {
+    anyhow::ensure!(
+        !field.is_empty()
+            && field
+                .chars()
+                .all(|el| el.is_ascii_alphanumeric() || el == '_'),
+        "invalid vmstat field `{field}`, expected letters, digits and underscores only"
+    );
+
+    Ok(())
+}
+
+/// Whether `field` is the current number of something (e.g., `nr_dirty` pages), which may go down,
+/// rather than an event counter.
+pub fn is_gauge(field: &str) -> bool {
+    field.starts_with("nr_")
+}
+
+/// Event counters and current values of the virtual memory subsystem.
+#[derive(Debug)]
+pub struct VmStat {
+    /// Key is the name of the field (e.g., `pgfault`), value is the number of events since boot
+    /// or, for [gauges](is_gauge), the current value.
+    pub counters: HashMap<String, u64>,
+}
+
+impl VmStat {
+    /// Retrieve the current counters from `/proc/vmstat`.
+    pub async fn current() -> Result<Self> {
+        let stats = smol::unblock(|| procfs::vmstat().context("reading /proc/vmstat")).await?;
+
+        let counters = stats
+            .into_iter()
+            .filter_map(|(key, val)| Some((key, u64::try_from(val).ok()?)))
+            .collect();
+
+        Ok(Self { counters })
+    }
+}
@@ -0,0 +1,374 @@
+//! Prometheus protobuf exposition format (delimited `io.prometheus.client.MetricFamily`).
+//!
+//! The messages mirror `metrics.proto` from `prometheus/client_model`. They are converted from
+//! the OpenMetrics data model produced by the protobuf encoder of `prometheus-client`, which
+//! Prometheus does not accept when scraping.
+
+use anyhow::Result;
+use prometheus_client::encoding::protobuf::openmetrics_data_model as om;
+use prometheus_client::registry::Registry;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct LabelPair {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub value: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MetricType {
+    Counter = 0,
+    Gauge = 1,
+    Summary = 2,
+    Untyped = 3,
+    Histogram = 4,
+}
+
+impl MetricType {
+    /// Name of the type as used in the Prometheus text format.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Summary => "summary",
+            Self::Untyped => "untyped",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Gauge {
+    #[prost(double, optional, tag = "1")]
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Counter {
+    #[prost(double, optional, tag = "1")]
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Quantile {
+    #[prost(double, optional, tag = "1")]
+    pub quantile: Option<f64>,
+    #[prost(double, optional, tag = "2")]
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Summary {
+    #[prost(uint64, optional, tag = "1")]
+    pub sample_count: Option<u64>,
+    #[prost(double, optional, tag = "2")]
+    pub sample_sum: Option<f64>,
+    #[prost(message, repeated, tag = "3")]
+    pub quantile: Vec<Quantile>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Untyped {
+    #[prost(double, optional, tag = "1")]
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Histogram {
+    #[prost(uint64, optional, tag = "1")]
+    pub sample_count: Option<u64>,
+    #[prost(double, optional, tag = "2")]
+    pub sample_sum: Option<f64>,
+    #[prost(message, repeated, tag = "3")]
+    pub bucket: Vec<Bucket>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Bucket {
+    #[prost(uint64, optional, tag = "1")]
+    pub cumulative_count: Option<u64>,
+    #[prost(double, optional, tag = "2")]
+    pub upper_bound: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(message, repeated, tag = "1")]
+    pub label: Vec<LabelPair>,
+    #[prost(message, optional, tag = "2")]
+    pub gauge: Option<Gauge>,
+    #[prost(message, optional, tag = "3")]
+    pub counter: Option<Counter>,
+    #[prost(message, optional, tag = "4")]
+    pub summary: Option<Summary>,
+    #[prost(message, optional, tag = "5")]
+    pub untyped: Option<Untyped>,
+    #[prost(int64, optional, tag = "6")]
+    pub timestamp_ms: Option<i64>,
+    #[prost(message, optional, tag = "7")]
+    pub histogram: Option<Histogram>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MetricFamily {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub help: Option<String>,
+    #[prost(enumeration = "MetricType", optional, tag = "3")]
+    pub r#type: Option<i32>,
+    #[prost(message, repeated, tag = "4")]
+    pub metric: Vec<Metric>,
+}
+
+/// Encode the metrics registered in `registry` as metric families.
+pub fn from_registry(registry: &Registry) -> Result<Vec<MetricFamily>> {
+    let metric_set = prometheus_client::encoding::protobuf::encode(registry)?;
+    let families = metric_set
+        .metric_families
+        .into_iter()
+        .map(MetricFamily::from)
+        .collect();
+
+    Ok(families)
+}
+
+/// Encode `families` as length-delimited messages.
+pub fn encode_delimited(families: &[MetricFamily]) -> Result<Vec<u8>> {
+    let len = families
+        .iter()
+        .map(|el| prost::length_delimiter_len(el.encoded_len()) + el.encoded_len())
+        .sum();
+    let mut buf = Vec::with_capacity(len);
+    for family in families {
+        family.encode_length_delimited(&mut buf)?;
+    }
+
+    Ok(buf)
+}
+
+impl From<om::MetricFamily> for MetricFamily {
+    fn from(family: om::MetricFamily) -> Self {
+        let om_type = family.r#type();
+        // Named like in the OpenMetrics text, which appends the unit.
+        let om_name = if family.unit.is_empty() {
+            family.name
+        } else {
+            format!("{}_{}", family.name, family.unit)
+        };
+        let (name, metric_type) = match om_type {
+            om::MetricType::Counter => (format!("{om_name}_total"), MetricType::Counter),
+            om::MetricType::Gauge | om::MetricType::StateSet => {
+                (om_name.clone(), MetricType::Gauge)
+            }
+            om::MetricType::Info => (format!("{om_name}_info"), MetricType::Gauge),
+            om::MetricType::Histogram | om::MetricType::GaugeHistogram => {
+                (om_name.clone(), MetricType::Histogram)
+            }
+            om::MetricType::Summary => (om_name.clone(), MetricType::Summary),
+            om::MetricType::Unknown => (om_name.clone(), MetricType::Untyped),
+        };
+
+        let mut metrics = Vec::with_capacity(family.metrics.len());
+        for metric in family.metrics {
+            let labels = metric
+                .labels
+                .into_iter()
+                .map(LabelPair::from)
+                .collect::<Vec<_>>();
+            for point in metric.metric_points {
+                Metric::push_point(&mut metrics, &om_name, &labels, point);
+            }
+        }
+
+        Self {
+            name: Some(name),
+            help: Some(family.help),
+            r#type: Some(metric_type as i32),
+            metric: metrics,
+        }
+    }
+}
+
+impl From<om::Label> for LabelPair {
+    fn from(label: om::Label) -> Self {
+        Self {
+            name: Some(label.name),
+            value: Some(label.value),
+        }
+    }
+}
+
+impl Metric {
+    /// Convert `point` of the family `om_name` with `labels` and append it to `metrics`.
+    ///
+    /// A state set results in one metric per state, labeled with the family name, like in the
+    /// OpenMetrics text.
+    fn push_point(
+        metrics: &mut Vec<Self>,
+        om_name: &str,
+        labels: &[LabelPair],
+        point: om::MetricPoint,
+    ) {
+        let metric = Self {
+            label: labels.to_vec(),
+            timestamp_ms: point
+                .timestamp
+                .map(|el| el.seconds * 1_000 + i64::from(el.nanos / 1_000_000_i32)),
+            ..Self::default()
+        };
+        let Some(point_value) = point.value else {
+            return;
+        };
+
+        match point_value {
+            om::metric_point::Value::UnknownValue(unknown) => metrics.push(Self {
+                untyped: Some(Untyped {
+                    value: unknown.value.as_ref().map(unknown_value),
+                }),
+                ..metric
+            }),
+            om::metric_point::Value::GaugeValue(gauge) => metrics.push(Self {
+                gauge: Some(Gauge {
+                    value: gauge.value.as_ref().map(gauge_value),
+                }),
+                ..metric
+            }),
+            om::metric_point::Value::CounterValue(counter) => metrics.push(Self {
+                counter: Some(Counter {
+                    value: counter.total.as_ref().map(counter_total),
+                }),
+                ..metric
+            }),
+            om::metric_point::Value::HistogramValue(histogram) => metrics.push(Self {
+                histogram: Some(histogram.into()),
+                ..metric
+            }),
+            om::metric_point::Value::StateSetValue(state_set) => {
+                for state in state_set.states {
+                    let mut label = metric.label.clone();
+                    label.push(LabelPair {
+                        name: Some(om_name.to_owned()),
+                        value: Some(state.name),
+                    });
+                    let enabled = if state.enabled { 1.0_f64 } else { 0.0_f64 };
+                    metrics.push(Self {
+                        label,
+                        gauge: Some(Gauge {
+                            value: Some(enabled),
+                        }),
+                        ..metric.clone()
+                    });
+                }
+            }
+            om::metric_point::Value::InfoValue(info) => {
+                let mut label = metric.label.clone();
+                label.extend(info.info.into_iter().map(LabelPair::from));
+                metrics.push(Self {
+                    label,
+                    gauge: Some(Gauge {
+                        value: Some(1.0_f64),
+                    }),
+                    ..metric
+                });
+            }
+            om::metric_point::Value::SummaryValue(summary) => metrics.push(Self {
+                summary: Some(summary.into()),
+                ..metric
+            }),
+        }
+    }
+}
+
+impl From<om::HistogramValue> for Histogram {
+    fn from(histogram: om::HistogramValue) -> Self {
+        // Bucket counts are per bucket in the OpenMetrics data model, but cumulative in the
+        // Prometheus one.
+        let mut cumulative_count = 0;
+        let bucket = histogram
+            .buckets
+            .iter()
+            .map(|el| {
+                cumulative_count += el.count;
+                // The last bucket ends at `f64::MAX`, which the text encoder writes as `+Inf`.
+                let upper_bound = if el.upper_bound >= f64::MAX {
+                    f64::INFINITY
+                } else {
+                    el.upper_bound
+                };
+                Bucket {
+                    cumulative_count: Some(cumulative_count),
+                    upper_bound: Some(upper_bound),
+                }
+            })
+            .collect();
+
+        Self {
+            sample_count: Some(histogram.count),
+            sample_sum: histogram.sum.as_ref().map(histogram_sum),
+            bucket,
+        }
+    }
+}
+
+impl From<om::SummaryValue> for Summary {
+    fn from(summary: om::SummaryValue) -> Self {
+        let quantile = summary
+            .quantile
+            .iter()
+            .map(|el| Quantile {
+                quantile: Some(el.quantile),
+                value: Some(el.value),
+            })
+            .collect();
+
+        Self {
+            sample_count: Some(summary.count),
+            sample_sum: summary.sum.as_ref().map(summary_sum),
+            quantile,
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn unknown_value(value: &om::unknown_value::Value) -> f64 {
+    match *value {
+        om::unknown_value::Value::DoubleValue(el) => el,
+        om::unknown_value::Value::IntValue(el) => el as f64,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn gauge_value(value: &om::gauge_value::Value) -> f64 {
+    match *value {
+        om::gauge_value::Value::DoubleValue(el) => el,
+        om::gauge_value::Value::IntValue(el) => el as f64,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn counter_total(total: &om::counter_value::Total) -> f64 {
+    match *total {
+        om::counter_value::Total::DoubleValue(el) => el,
+        om::counter_value::Total::IntValue(el) => el as f64,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn histogram_sum(sum: &om::histogram_value::Sum) -> f64 {
+    match *sum {
+        om::histogram_value::Sum::DoubleValue(el) => el,
+        om::histogram_value::Sum::IntValue(el) => el as f64,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn summary_sum(sum: &om::summary_value::Sum) -> f64 {
+    match *sum {
+        om::summary_value::Sum::DoubleValue(el) => el,
+        om::summary_value::Sum::IntValue(el) => el as f64,
+    }
+}
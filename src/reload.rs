@@ -0,0 +1,168 @@
+//! Reloading of the configuration at runtime.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use async_signal::{Signal, Signals};
+use smol::stream::StreamExt;
+
+use crate::args::CliArgs;
+use crate::auth::Auth;
+use crate::collector::Collector;
+use crate::config::{LogConfig, ServerConfig, UserConfig};
+use crate::systemd;
+use crate::tls::Tls;
+
+/// Interval in which the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reloads the configuration, and the TLS certificate and bearer tokens if enabled.
+#[derive(Debug, Clone)]
+pub struct Reloader {
+    /// Path of the configuration file, and the overrides applied to it.
+    args: CliArgs,
+    /// Settings of the running configuration that only take effect on restart.
+    server: ServerConfig,
+    log: LogConfig,
+    collector: Collector,
+    tls: Option<Tls>,
+    auth: Option<Auth>,
+}
+
+impl Reloader {
+    pub fn new(
+        args: CliArgs,
+        config: &UserConfig,
+        collector: Collector,
+        tls: Option<Tls>,
+        auth: Option<Auth>,
+    ) -> Self {
+        Self {
+            args,
+            server: config.server.clone(),
+            log: config.log.clone(),
+            collector,
+            tls,
+            auth,
+        }
+    }
+
+    /// Reload whenever `SIGHUP` is received.
+    pub async fn on_sighup(self) -> Result<()> {
+        let mut signals = Signals::new([Signal::Hup]).context("registering SIGHUP handler")?;
+        while let Some(signal) = signals.next().await {
+            signal.context("receiving signal")?;
+            tracing::info!("received SIGHUP");
+            self.reload().await;
+        }
+
+        Ok(())
+    }
+
+    /// Reload whenever the modification time of the configuration file changes.
+    pub async fn on_change(self) {
+        let path = &self.args.config_path;
+        let mut last_modified = modified(path).await;
+        let mut ticker = smol::Timer::interval(WATCH_INTERVAL);
+        while ticker.next().await.is_some() {
+            let modified = modified(path).await;
+            if modified == last_modified {
+                continue;
+            }
+
+            tracing::info!("config file changed");
+            last_modified = modified;
+            self.reload().await;
+        }
+    }
+
+    /// Reload the configuration, TLS certificate and bearer tokens. Keeps the current state of
+    /// each if reloading it fails.
+    async fn reload(&self) {
+        self.reload_config().await;
+        if let Some(tls) = &self.tls {
+            match tls.reload().await {
+                Ok(()) => tracing::info!("TLS certificate reloaded"),
+                Err(err) => {
+                    tracing::error!(
+                        "reloading TLS certificate failed, keeping current one: {err:#}"
+                    );
+                }
+            }
+        }
+        if let Some(auth) = &self.auth {
+            match auth.reload().await {
+                Ok(()) => tracing::info!("bearer tokens reloaded"),
+                Err(err) => {
+                    tracing::error!(
+                        "reloading bearer tokens failed, keeping current ones: {err:#}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Re-read the configuration and rebuild all collectors from it. Keeps the current
+    /// collectors running if that fails.
+    async fn reload_config(&self) {
+        let path = &self.args.config_path;
+        tracing::info!("reloading config from {}", path.display());
+        let mut config = match UserConfig::from_path(path).await {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!("reloading config failed, keeping current config: {err:#}");
+                return;
+            }
+        };
+        config.apply_args(&self.args);
+        let changed = self.restart_required(&config);
+        if !changed.is_empty() {
+            tracing::warn!(
+                "changes to {} only take effect on restart, keeping the running values",
+                changed.join(", ")
+            );
+        }
+
+        let collector = &self.collector;
+        match collector.reload(&config).await {
+            Ok(()) => {
+                tracing::info!("config reloaded");
+                if let Err(err) = systemd::notify_status(&collector.names().await) {
+                    tracing::warn!("notifying systemd failed: {err:#}");
+                }
+            }
+            Err(err) => {
+                tracing::error!("recreating collectors failed, keeping current config: {err:#}");
+            }
+        }
+    }
+
+    /// Names of the settings in `config` that differ from the running ones, but are not reloaded.
+    /// Only the metrics, the TLS certificate and the bearer tokens are.
+    fn restart_required(&self, config: &UserConfig) -> Vec<&'static str> {
+        let (running, server) = (&self.server, &config.server);
+        [
+            ("server.listen", running.listen != server.listen),
+            ("server.port", running.port != server.port),
+            ("server.tls", running.tls != server.tls),
+            ("server.auth", running.auth != server.auth),
+            ("server.allow_ips", running.allow_ips != server.allow_ips),
+            ("server.deny_ips", running.deny_ips != server.deny_ips),
+            ("server.socket", running.socket != server.socket),
+            (
+                "server.grace_period_ms",
+                running.grace_period != server.grace_period,
+            ),
+            ("log", self.log != config.log),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// Modification time of the file at `path`, or `None` if it can't be retrieved.
+async fn modified(path: &Path) -> Option<SystemTime> {
+    smol::fs::metadata(path).await.ok()?.modified().ok()
+}
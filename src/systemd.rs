@@ -0,0 +1,149 @@
+//! Integration with systemd.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use nix::sys::socket::{
+    AddressFamily, SockaddrLike, SockaddrStorage, getsockname, getsockopt, sockopt,
+};
+use smol::stream::StreamExt;
+
+use crate::collector::Collector;
+
+/// First file descriptor passed by systemd, see `sd_listen_fds(3)`.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether the sockets passed by systemd have been taken already.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A listening socket passed by systemd.
+#[derive(Debug)]
+pub enum ListenSocket {
+    Tcp(std::net::TcpListener),
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Take the listening sockets passed via socket activation (`LISTEN_FDS` and `LISTEN_PID`).
+///
+/// Returns an empty list if litemon was not socket-activated, or when called again.
+pub fn listen_fds() -> Result<Vec<ListenSocket>> {
+    let Ok(pid) = std::env::var("LISTEN_PID") else {
+        return Ok(Vec::new());
+    };
+    // The variables are inherited by children of the activated process, so only use them if
+    // they are meant for us.
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let count = std::env::var("LISTEN_FDS")
+        .context("LISTEN_PID is set, but LISTEN_FDS is not")?
+        .parse::<RawFd>()
+        .context("parsing LISTEN_FDS")?;
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count))
+        .map(|raw| {
+            // SAFETY: systemd passes `LISTEN_FDS` open descriptors starting at 3 to the process
+            // in `LISTEN_PID`, which is us. `TAKEN` ensures they are only taken once.
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+            fcntl(&fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+                .with_context(|| format!("setting FD_CLOEXEC on fd {raw}"))?;
+            socket_from_fd(fd).with_context(|| format!("adopting socket fd {raw} from systemd"))
+        })
+        .collect()
+}
+
+/// Wrap `fd`, which must be a listening stream socket, in the listener for its address family.
+fn socket_from_fd(fd: OwnedFd) -> Result<ListenSocket> {
+    anyhow::ensure!(
+        getsockopt(&fd, sockopt::AcceptConn)?,
+        "not a listening socket, use `ListenStream=` in the socket unit"
+    );
+    let addr = getsockname::<SockaddrStorage>(fd.as_raw_fd())?;
+    match addr.family() {
+        Some(AddressFamily::Inet | AddressFamily::Inet6) => {
+            Ok(ListenSocket::Tcp(std::net::TcpListener::from(fd)))
+        }
+        Some(AddressFamily::Unix) => Ok(ListenSocket::Unix(
+            std::os::unix::net::UnixListener::from(fd),
+        )),
+        family => anyhow::bail!("unsupported address family {family:?}"),
+    }
+}
+
+/// Send `state` (e.g., `READY=1`) to the service manager, see `sd_notify(3)`.
+///
+/// Does nothing if `NOTIFY_SOCKET` is unset, e.g., when not started by systemd with `Type=notify`.
+pub fn notify(state: &str) -> Result<()> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let addr = match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+
+    let socket = UnixDatagram::unbound().context("creating notification socket")?;
+    socket
+        .send_to_addr(state.as_bytes(), &addr)
+        .with_context(|| format!("notifying systemd via {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Report that litemon is ready, with a status listing the enabled `collectors`.
+pub fn notify_ready(collectors: &[&str]) -> Result<()> {
+    notify(&format!("READY=1\n{}", status(collectors)))
+}
+
+/// Update the status shown by `systemctl status` after the `collectors` changed.
+pub fn notify_status(collectors: &[&str]) -> Result<()> {
+    notify(&status(collectors))
+}
+
+/// `STATUS=` message listing the enabled `collectors`.
+fn status(collectors: &[&str]) -> String {
+    format!(
+        "STATUS=Serving {} collectors: {}",
+        collectors.len(),
+        collectors.join(", ")
+    )
+}
+
+/// Interval in which systemd expects watchdog pings, if `WatchdogSec=` is set for litemon.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Send `WATCHDOG=1` at half of `interval`, forever, as long as `collector` is still sampling
+/// metrics.
+///
+/// Run this on the executor serving requests, so a wedged runtime or stuck sampling tasks stop
+/// the pings and litemon gets restarted by systemd.
+pub async fn watchdog(interval: Duration, collector: Collector) {
+    let mut ticker = smol::Timer::interval(interval / 2);
+    while ticker.next().await.is_some() {
+        if !collector.is_alive().await {
+            tracing::warn!("collectors stopped sampling, skipping watchdog ping");
+            continue;
+        }
+        if let Err(err) = notify("WATCHDOG=1") {
+            tracing::warn!("sending watchdog ping failed: {err:#}");
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! TLS termination for the HTTP server.
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures_rustls::TlsAcceptor;
+use futures_rustls::rustls::crypto::{CryptoProvider, ring};
+use futures_rustls::rustls::pki_types::pem::PemObject;
+use futures_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use futures_rustls::rustls::server::WebPkiClientVerifier;
+use futures_rustls::rustls::server::danger::ClientCertVerifier;
+use futures_rustls::rustls::{RootCertStore, ServerConfig};
+use futures_rustls::server::TlsStream;
+use smol::io::{AsyncRead, AsyncWrite};
+use smol::lock::RwLock;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+use crate::config::TlsConfig;
+
+/// TLS acceptor whose certificate can be replaced at runtime.
+#[derive(Clone)]
+pub struct Tls {
+    config: TlsConfig,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+}
+
+impl std::fmt::Debug for Tls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tls")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tls {
+    /// Load the certificate and key referenced by `config`.
+    pub async fn new(config: &TlsConfig) -> Result<Self> {
+        let acceptor = load(config).await?;
+        Ok(Self {
+            config: config.clone(),
+            acceptor: Arc::new(RwLock::new(acceptor)),
+        })
+    }
+
+    /// Re-read the certificate and key from disk. New connections use the new certificate,
+    /// established connections are not affected. Keeps the current certificate if that fails.
+    pub async fn reload(&self) -> Result<()> {
+        let acceptor = load(&self.config).await?;
+        *self.acceptor.write().await = acceptor;
+
+        Ok(())
+    }
+
+    /// Perform the TLS handshake on `stream`.
+    ///
+    /// If [`TlsConfig::allowed_names`] is set, the client certificate must carry one of them as
+    /// subject CN or SAN.
+    pub async fn accept<S>(&self, stream: S) -> Result<TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let acceptor = self.acceptor.read().await.clone();
+        let stream = acceptor.accept(stream).await.context("TLS handshake")?;
+
+        if !self.config.allowed_names.is_empty() {
+            let (_, conn) = stream.get_ref();
+            let cert = conn
+                .peer_certificates()
+                .and_then(|el| el.first())
+                .context("client did not present a certificate")?;
+            check_allowed_names(cert, &self.config.allowed_names)?;
+        }
+
+        Ok(stream)
+    }
+}
+
+/// Check that `cert` carries one of `allowed_names` as subject CN or SAN.
+pub fn check_allowed_names(cert: &CertificateDer<'_>, allowed_names: &[String]) -> Result<()> {
+    let names = cert_names(cert)?;
+    anyhow::ensure!(
+        names.iter().any(|name| allowed_names.contains(name)),
+        "client certificate names not allowed: {}",
+        names.join(", ")
+    );
+
+    Ok(())
+}
+
+/// Subject CNs and SANs (DNS names, email addresses, URIs and IP addresses) of `cert`.
+fn cert_names(cert: &CertificateDer<'_>) -> Result<Vec<String>> {
+    let (_, cert) = X509Certificate::from_der(cert).context("parsing client certificate")?;
+
+    let mut ret = cert
+        .subject()
+        .iter_common_name()
+        .filter_map(|el| el.as_str().ok())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(name)
+                | GeneralName::RFC822Name(name)
+                | GeneralName::URI(name) => ret.push((*name).to_owned()),
+                GeneralName::IPAddress(addr) => {
+                    let addr = match addr.len() {
+                        4 => <[u8; 4]>::try_from(*addr).ok().map(IpAddr::from),
+                        16 => <[u8; 16]>::try_from(*addr).ok().map(IpAddr::from),
+                        _ => None,
+                    };
+                    ret.extend(addr.map(|el| el.to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Create an acceptor from the certificate and key referenced by `config`.
+async fn load(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let cert = smol::fs::read(&config.cert)
+        .await
+        .with_context(|| format!("reading certificate {}", config.cert.display()))?;
+    let certs = CertificateDer::pem_slice_iter(&cert)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parsing certificate {}", config.cert.display()))?;
+    anyhow::ensure!(
+        !certs.is_empty(),
+        "no certificate found in {}",
+        config.cert.display()
+    );
+
+    let key = smol::fs::read(&config.key)
+        .await
+        .with_context(|| format!("reading private key {}", config.key.display()))?;
+    let key = PrivateKeyDer::from_pem_slice(&key)
+        .with_context(|| format!("parsing private key {}", config.key.display()))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .context("configuring TLS protocol versions")?;
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let verifier = client_verifier(client_ca, provider).await?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .context("configuring TLS certificate")?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Verifier requiring client certificates signed by one of the CAs in the bundle at `path`.
+async fn client_verifier(
+    path: &Path,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn ClientCertVerifier>> {
+    let bundle = smol::fs::read(path)
+        .await
+        .with_context(|| format!("reading client CA bundle {}", path.display()))?;
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(&bundle) {
+        let cert = cert.with_context(|| format!("parsing client CA bundle {}", path.display()))?;
+        roots
+            .add(cert)
+            .with_context(|| format!("adding client CA from {}", path.display()))?;
+    }
+    anyhow::ensure!(!roots.is_empty(), "no CA found in {}", path.display());
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .context("configuring client certificate verification")
+}
@@ -0,0 +1,49 @@
+//! Tests for the acl module.
+
+use std::net::IpAddr;
+
+use litemon::acl::IpFilter;
+use litemon::config::ServerConfig;
+
+fn ip_filter(allow: &[&str], deny: &[&str]) -> IpFilter {
+    let config = ServerConfig {
+        allow_ips: allow
+            .iter()
+            .map(|el| el.parse().expect("network"))
+            .collect(),
+        deny_ips: deny.iter().map(|el| el.parse().expect("network")).collect(),
+        ..ServerConfig::default()
+    };
+    IpFilter::new(&config)
+}
+
+fn ip(value: &str) -> IpAddr {
+    value.parse().expect("IP address")
+}
+
+#[test]
+fn allow_all_by_default() {
+    let filter = ip_filter(&[], &[]);
+    assert!(filter.is_allowed(ip("192.0.2.1")));
+    assert!(filter.is_allowed(ip("2001:db8::1")));
+}
+
+#[test]
+fn deny_before_allow() {
+    let filter = ip_filter(&["10.0.0.0/8"], &["10.1.0.0/16"]);
+    assert!(filter.is_allowed(ip("10.2.0.1")));
+    assert!(!filter.is_allowed(ip("10.1.0.1")));
+    assert!(!filter.is_allowed(ip("192.0.2.1")));
+
+    // A denied network wins even if the peer is also allowed explicitly.
+    let overlapping = ip_filter(&["192.0.2.1/32"], &["192.0.2.0/24"]);
+    assert!(!overlapping.is_allowed(ip("192.0.2.1")));
+}
+
+#[test]
+fn ipv4_mapped_addresses() {
+    let filter = ip_filter(&["192.0.2.0/24"], &["192.0.2.128/25"]);
+    assert!(filter.is_allowed(ip("::ffff:192.0.2.1")));
+    assert!(!filter.is_allowed(ip("::ffff:192.0.2.200")));
+    assert!(!filter.is_allowed(ip("::ffff:198.51.100.1")));
+}
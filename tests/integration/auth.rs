@@ -0,0 +1,89 @@
+//! Tests for the auth module.
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use http::{HeaderMap, HeaderValue, header};
+use litemon::auth::Auth;
+use litemon::config::AuthConfig;
+
+/// bcrypt hash of `secret` with the minimum cost, to keep the tests fast.
+const SECRET_HASH: &str = "$2b$04$pi4U1lhZ3HJJJ.zqsqorVOWLrfB5goE0L0nZkehNb/NIHXTo8hWGq";
+
+fn authorization(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(value).expect("header value"),
+    );
+    headers
+}
+
+fn basic(username: &str, password: &str) -> HeaderMap {
+    let credentials = BASE64_STANDARD.encode(format!("{username}:{password}"));
+    authorization(&format!("Basic {credentials}"))
+}
+
+#[test]
+fn bearer_tokens() {
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let tokens = tmp.join("bearer_tokens_test");
+    std::fs::write(&tokens, "# scrapers\ntoken-one\n\n  token-two  \n").unwrap();
+    let config = AuthConfig {
+        basic_auth_users: Vec::new(),
+        bearer_token_file: Some(tokens.clone()),
+    };
+
+    smol::block_on(async move {
+        let auth = Auth::new(&config).await.unwrap();
+        assert!(auth.is_authorized(&authorization("Bearer token-one")).await);
+        assert!(auth.is_authorized(&authorization("bearer token-two")).await);
+        assert!(!auth.is_authorized(&authorization("Bearer token")).await);
+        assert!(
+            !auth
+                .is_authorized(&authorization("Bearer token-one2"))
+                .await
+        );
+        assert!(
+            !auth
+                .is_authorized(&authorization("Bearer # scrapers"))
+                .await
+        );
+        assert!(!auth.is_authorized(&authorization("Bearer")).await);
+        assert!(!auth.is_authorized(&authorization("Token token-one")).await);
+        assert!(!auth.is_authorized(&HeaderMap::new()).await);
+        // Basic auth is not enabled.
+        assert!(!auth.is_authorized(&basic("token-one", "")).await);
+
+        std::fs::write(&tokens, "token-three\n").unwrap();
+        auth.reload().await.unwrap();
+        assert!(!auth.is_authorized(&authorization("Bearer token-one")).await);
+        assert!(
+            auth.is_authorized(&authorization("Bearer token-three"))
+                .await
+        );
+    });
+}
+
+#[test]
+fn basic_auth() {
+    let config = AuthConfig {
+        basic_auth_users: vec![("prometheus".to_owned(), SECRET_HASH.to_owned())],
+        bearer_token_file: None,
+    };
+
+    smol::block_on(async move {
+        let auth = Auth::new(&config).await.unwrap();
+        assert!(auth.is_authorized(&basic("prometheus", "secret")).await);
+        // Served from the cache of verified credentials.
+        assert!(auth.is_authorized(&basic("prometheus", "secret")).await);
+        assert!(!auth.is_authorized(&basic("prometheus", "Secret")).await);
+        assert!(!auth.is_authorized(&basic("prometheus", "")).await);
+        assert!(!auth.is_authorized(&basic("grafana", "secret")).await);
+        assert!(!auth.is_authorized(&authorization("Basic not-base64")).await);
+        assert!(!auth.is_authorized(&authorization("Bearer secret")).await);
+        assert_eq!(auth.challenges().len(), 1);
+    });
+}
@@ -1,6 +1,6 @@
 //! Tests for the `CliArgs`.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use litemon::args::CliArgs;
 use predicates::str::contains;
@@ -19,17 +19,22 @@ fn parse_args_short() {
         "localhost",
         "-P",
         "1234",
+        "-w",
         "test/config.kdl",
     ])
     .unwrap();
     let CliArgs {
-        listen_address,
+        listen_addresses,
         listen_port,
         config_path,
+        watch_config,
+        check_config,
     } = args;
-    assert_eq!(listen_address, "localhost");
-    assert_eq!(listen_port, 1234);
+    assert_eq!(listen_addresses, ["localhost"]);
+    assert_eq!(listen_port, Some(1234));
     assert_eq!(config_path, Path::new("test/config.kdl"));
+    assert!(watch_config);
+    assert!(!check_config);
 }
 
 #[test]
@@ -38,19 +43,70 @@ fn parse_args_long() {
         "litemon",
         "--listen",
         "localhost",
+        "--listen",
+        "unix:/run/litemon.sock",
         "--port",
         "1234",
+        "--watch-config",
         "test/config.kdl",
     ])
     .unwrap();
     let CliArgs {
-        listen_address,
+        listen_addresses,
         listen_port,
         config_path,
+        watch_config,
+        check_config,
     } = args;
-    assert_eq!(listen_address, "localhost");
-    assert_eq!(listen_port, 1234);
+    assert_eq!(listen_addresses, ["localhost", "unix:/run/litemon.sock"]);
+    assert_eq!(listen_port, Some(1234));
     assert_eq!(config_path, Path::new("test/config.kdl"));
+    assert!(watch_config);
+    assert!(!check_config);
+}
+
+#[test]
+fn parse_args_check_config() {
+    let args = CliArgs::from_args(["litemon", "--check-config", "test/config.kdl"]).unwrap();
+    assert!(args.check_config);
+    assert_eq!(args.config_path, Path::new("test/config.kdl"));
+}
+
+#[test]
+fn check_config() {
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+
+    let valid = tmp.join("check_config_valid.kdl");
+    std::fs::write(&valid, "metrics {\n  loadavg enabled=#false\n}\n").unwrap();
+    assert_cmd::Command::cargo_bin("litemon")
+        .unwrap()
+        .arg("--check-config")
+        .arg(&valid)
+        .assert()
+        .success()
+        .stdout(contains("loadavg enabled=#false"));
+
+    // Overrides from the command line are part of the effective configuration.
+    assert_cmd::Command::cargo_bin("litemon")
+        .unwrap()
+        .args(["--port", "1234", "--check-config"])
+        .arg(&valid)
+        .assert()
+        .success()
+        .stdout(contains("port 1234"));
+
+    let invalid = tmp.join("check_config_invalid.kdl");
+    std::fs::write(&invalid, "metrics {\n  loadavg enable=#true\n}\n").unwrap();
+    assert_cmd::Command::cargo_bin("litemon")
+        .unwrap()
+        .arg("--check-config")
+        .arg(&invalid)
+        .assert()
+        .failure()
+        .stderr(contains(
+            "check_config_invalid.kdl:2:11: unknown property `enable`",
+        ));
 }
 
 #[test]
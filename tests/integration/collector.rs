@@ -0,0 +1,190 @@
+//! Tests for the collector module.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use litemon::collector::Collector;
+use litemon::metrics::collector::VmStatCollector;
+use litemon::metrics::{DynFuture, Metric};
+use prometheus_client::registry::Registry;
+
+/// How a [`FakeMetric`] collection ends.
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    Succeed,
+    Fail,
+    Hang,
+}
+
+/// Metric counting its collections, which end according to its [`Outcome`].
+#[derive(Debug)]
+struct FakeMetric {
+    name: &'static str,
+    outcome: Outcome,
+    collections: Arc<AtomicUsize>,
+}
+
+impl FakeMetric {
+    fn boxed(name: &'static str, outcome: Outcome) -> (Box<dyn Metric>, Arc<AtomicUsize>) {
+        let collections = Arc::new(AtomicUsize::new(0));
+        let metric = Box::new(Self {
+            name,
+            outcome,
+            collections: Arc::clone(&collections),
+        });
+        (metric, collections)
+    }
+}
+
+impl Metric for FakeMetric {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn register(&self, _registry: &mut Registry) {}
+
+    fn collect(&self) -> DynFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.collections.fetch_add(1, Ordering::Relaxed);
+            match self.outcome {
+                Outcome::Succeed => Ok(()),
+                Outcome::Fail => anyhow::bail!("collection failed"),
+                Outcome::Hang => std::future::pending().await,
+            }
+        })
+    }
+}
+
+/// Value of the scrape stat `name` of `collector` in `encoded`.
+fn stat<'a>(encoded: &'a str, name: &str, collector: &str) -> &'a str {
+    let prefix = format!("litemon_scrape_collector_{name}{{collector=\"{collector}\"}} ");
+    encoded
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .expect("scrape stat is missing")
+}
+
+const TIMEOUT: Duration = Duration::from_millis(20);
+const INTERVAL: Duration = Duration::from_millis(50);
+
+#[test]
+fn failing_collector_does_not_affect_others() {
+    smol::block_on(async {
+        let collector = Collector::new();
+        let (ok, ok_count) = FakeMetric::boxed("ok", Outcome::Succeed);
+        let (failing, failing_count) = FakeMetric::boxed("failing", Outcome::Fail);
+        collector.push(ok, TIMEOUT, INTERVAL).await;
+        collector.push(failing, TIMEOUT, INTERVAL).await;
+        collector.register().await.unwrap();
+        collector.start().await;
+
+        smol::Timer::after(INTERVAL * 4).await;
+        let encoded = collector.encode(&[]).await.unwrap();
+        assert_eq!(stat(&encoded, "success", "ok"), "1");
+        assert_eq!(stat(&encoded, "success", "failing"), "0");
+        assert_eq!(stat(&encoded, "timed_out", "failing"), "0");
+        // Both keep being sampled on every tick.
+        assert!(ok_count.load(Ordering::Relaxed) >= 3);
+        assert!(failing_count.load(Ordering::Relaxed) >= 3);
+        assert!(collector.is_ready().await);
+        assert!(collector.is_alive().await);
+
+        collector.shutdown().await;
+    });
+}
+
+#[test]
+fn timed_out_collector_is_not_restarted() {
+    smol::block_on(async {
+        let collector = Collector::new();
+        let (ok, ok_count) = FakeMetric::boxed("ok", Outcome::Succeed);
+        let (hanging, hanging_count) = FakeMetric::boxed("hanging", Outcome::Hang);
+        collector.push(ok, TIMEOUT, INTERVAL).await;
+        collector.push(hanging, TIMEOUT, INTERVAL).await;
+        collector.register().await.unwrap();
+        collector.start().await;
+
+        smol::Timer::after(INTERVAL * 4).await;
+        let encoded = collector.encode(&[]).await.unwrap();
+        assert_eq!(stat(&encoded, "success", "ok"), "1");
+        assert_eq!(stat(&encoded, "timed_out", "ok"), "0");
+        assert_eq!(stat(&encoded, "success", "hanging"), "0");
+        assert_eq!(stat(&encoded, "timed_out", "hanging"), "1");
+        assert!(ok_count.load(Ordering::Relaxed) >= 3);
+        // The hanging collection is still in flight, so no new one has been started.
+        assert_eq!(hanging_count.load(Ordering::Relaxed), 1);
+        // The skipped ticks are reported, so the sampling task still counts as alive.
+        assert!(collector.is_ready().await);
+        assert!(collector.is_alive().await);
+
+        collector.shutdown().await;
+    });
+}
+
+#[test]
+fn collectors_run_on_their_own_interval() {
+    smol::block_on(async {
+        let collector = Collector::new();
+        let (fast, fast_count) = FakeMetric::boxed("fast", Outcome::Succeed);
+        let (slow, slow_count) = FakeMetric::boxed("slow", Outcome::Succeed);
+        collector.push(fast, TIMEOUT, INTERVAL).await;
+        collector.push(slow, TIMEOUT, Duration::from_secs(60)).await;
+        collector.register().await.unwrap();
+        collector.start().await;
+
+        smol::Timer::after(INTERVAL * 4).await;
+        assert!(fast_count.load(Ordering::Relaxed) >= 3);
+        // Collected once right away, then not before its interval has passed.
+        assert_eq!(slow_count.load(Ordering::Relaxed), 1);
+
+        collector.shutdown().await;
+    });
+}
+
+#[test]
+fn not_ready_or_alive_before_start() {
+    smol::block_on(async {
+        let collector = Collector::new();
+        let (ok, ok_count) = FakeMetric::boxed("ok", Outcome::Succeed);
+        collector.push(ok, TIMEOUT, INTERVAL).await;
+        collector.register().await.unwrap();
+
+        smol::Timer::after(INTERVAL * 2).await;
+        assert_eq!(ok_count.load(Ordering::Relaxed), 0);
+        assert!(!collector.is_ready().await);
+        assert!(!collector.is_alive().await);
+
+        collector.start().await;
+        smol::Timer::after(INTERVAL).await;
+        assert!(collector.is_ready().await);
+        assert!(collector.is_alive().await);
+
+        // Sampling stops on shutdown.
+        collector.shutdown().await;
+        assert!(!collector.is_alive().await);
+        let count = ok_count.load(Ordering::Relaxed);
+        smol::Timer::after(INTERVAL * 2).await;
+        assert_eq!(ok_count.load(Ordering::Relaxed), count);
+    });
+}
+
+#[test]
+fn vmstat_exports_current_values_as_gauges() {
+    let options =
+        hashbrown::HashMap::from([("fields".to_owned(), "pgfault,nr_free_pages".to_owned())]);
+    let metric = VmStatCollector::new(&options).unwrap();
+    let mut registry = Registry::default();
+    metric.register(&mut registry);
+    smol::block_on(metric.collect()).unwrap();
+
+    let mut encoded = String::new();
+    prometheus_client::encoding::text::encode(&mut encoded, &registry).unwrap();
+    assert!(encoded.contains("# TYPE litemon_vmstat_pgfault counter\n"));
+    assert!(encoded.contains("# TYPE litemon_vmstat_nr_free_pages gauge\n"));
+    assert!(encoded.contains("\nlitemon_vmstat_nr_free_pages "));
+
+    let duplicate = hashbrown::HashMap::from([("fields".to_owned(), "pgfault,pgfault".to_owned())]);
+    let err = VmStatCollector::new(&duplicate).unwrap_err();
+    assert!(err.to_string().contains("duplicate vmstat field `pgfault`"));
+}
@@ -0,0 +1,79 @@
+//! Tests for the compression module.
+
+use std::io::Read;
+
+use http::{HeaderMap, HeaderValue, header};
+use litemon::compression::Encoding;
+
+fn negotiate(accept_encoding: &'static str) -> Option<Encoding> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static(accept_encoding),
+    );
+    Encoding::negotiate(&headers)
+}
+
+#[test]
+fn negotiate_encoding() {
+    assert_eq!(
+        Encoding::negotiate(&HeaderMap::new()),
+        Some(Encoding::Identity)
+    );
+    assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("GZIP, deflate"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("br"), Some(Encoding::Identity));
+    // zstd is preferred if both are accepted equally.
+    assert_eq!(negotiate("gzip, zstd"), Some(Encoding::Zstd));
+    assert_eq!(negotiate("*"), Some(Encoding::Zstd));
+}
+
+#[test]
+fn negotiate_quality() {
+    assert_eq!(negotiate("zstd;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("zstd;q=0.5, gzip"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("gzip;q=0.5, *;q=0.9"), Some(Encoding::Zstd));
+    assert_eq!(negotiate("zstd;q=0, *"), Some(Encoding::Gzip));
+    assert_eq!(
+        negotiate("zstd;q=0.1, identity;q=0.5"),
+        Some(Encoding::Identity)
+    );
+    assert_eq!(negotiate("gzip; Q=0"), Some(Encoding::Identity));
+    // Invalid elements are ignored.
+    assert_eq!(negotiate("gzip;q=high, zstd"), Some(Encoding::Zstd));
+}
+
+#[test]
+fn negotiate_nothing_acceptable() {
+    assert_eq!(negotiate("identity;q=0"), None);
+    assert_eq!(negotiate("br, identity;q=0"), None);
+    assert_eq!(negotiate("*;q=0"), None);
+    assert_eq!(
+        negotiate("gzip;q=0, *;q=0, zstd;q=0.5"),
+        Some(Encoding::Zstd)
+    );
+}
+
+#[test]
+fn encode_roundtrip() {
+    let body = b"litemon_load_avg_1m 0.5\n".repeat(100);
+
+    let gzip = Encoding::Gzip.encode(body.clone()).unwrap();
+    assert!(gzip.len() < body.len());
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(gzip.as_slice())
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, body);
+
+    let zstd = Encoding::Zstd.encode(body.clone()).unwrap();
+    assert!(zstd.len() < body.len());
+    assert_eq!(zstd::decode_all(zstd.as_slice()).unwrap(), body);
+
+    assert_eq!(Encoding::Identity.encode(body.clone()).unwrap(), body);
+    assert_eq!(Encoding::Identity.header_value(), None);
+    assert_eq!(
+        Encoding::Zstd.header_value(),
+        Some(HeaderValue::from_static("zstd"))
+    );
+}
@@ -3,7 +3,8 @@
 use std::path::PathBuf;
 use std::time::Duration;
 
-use litemon::config::UserConfig;
+use litemon::args::CliArgs;
+use litemon::config::{ConfigErrors, ListenAddr, LogFormat, UserConfig};
 
 #[test]
 fn load_config_from_path() {
@@ -11,7 +12,9 @@ fn load_config_from_path() {
 metrics {
   cpu_seconds enabled=#true period_ms=200
   loadavg enabled=#false
-  memory_used enabled=#true
+  memory_used enabled=#true {
+    fields "Dirty" "HugePages_Total"
+  }
   systemd_unit_state enabled=#true {
     units "valkey.service" "postgresql.service"
   }
@@ -21,6 +24,9 @@ metrics {
   disk_usage enabled=#false {
     mountpoints "/"
   }
+  vmstat enabled=#true {
+    fields "pgfault" "oom_kill"
+  }
 }
         "#;
     let tmp =
@@ -31,12 +37,23 @@ metrics {
     smol::block_on(async move {
         let config = UserConfig::from_path(&filepath).await.unwrap();
         assert!(config.metrics.cpu_seconds.enabled);
-        assert_eq!(config.metrics.cpu_seconds.period, Duration::from_millis(200));
+        assert_eq!(
+            config.metrics.cpu_seconds.period,
+            Duration::from_millis(200)
+        );
         assert!(!config.metrics.loadavg.enabled);
+        assert!(config.metrics.memory_used.enabled);
+        assert_eq!(
+            config.metrics.memory_used.fields,
+            ["Dirty", "HugePages_Total"]
+        );
         assert!(config.metrics.systemd_unit_state.enabled);
         assert_eq!(config.metrics.systemd_unit_state.units.len(), 2);
         assert_eq!(config.metrics.systemd_unit_state.units[0], "valkey.service");
-        assert_eq!(config.metrics.systemd_unit_state.units[1], "postgresql.service");
+        assert_eq!(
+            config.metrics.systemd_unit_state.units[1],
+            "postgresql.service"
+        );
         assert!(config.metrics.network_throughput.enabled);
         assert_eq!(config.metrics.network_throughput.interfaces.len(), 2);
         assert_eq!(config.metrics.network_throughput.interfaces[0], "eth0");
@@ -44,5 +61,409 @@ metrics {
         assert!(!config.metrics.disk_usage.enabled);
         assert_eq!(config.metrics.disk_usage.mountpoints.len(), 1);
         assert_eq!(config.metrics.disk_usage.mountpoints[0], "/");
+        assert!(config.metrics.vmstat.enabled);
+        assert_eq!(config.metrics.vmstat.fields, ["pgfault", "oom_kill"]);
+    });
+}
+
+#[test]
+fn load_config_timeouts() {
+    let configstr = r#"
+metrics timeout_ms=2000 {
+  cpu_seconds enabled=#true period_ms=200
+  systemd_unit_state enabled=#true timeout_ms=500 {
+    units "valkey.service"
+  }
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("load_config_timeouts_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let config = UserConfig::from_path(&filepath).await.unwrap();
+        assert_eq!(config.metrics.timeout, Duration::from_secs(2));
+        assert_eq!(config.metrics.cpu_seconds.timeout, None);
+        assert_eq!(
+            config.metrics.systemd_unit_state.timeout,
+            Some(Duration::from_millis(500))
+        );
+    });
+}
+
+#[test]
+fn load_config_intervals() {
+    let configstr = r#"
+metrics interval_ms=30000 {
+  cpu_seconds enabled=#true interval_ms=10000
+  systemd_unit_state enabled=#true {
+    units "valkey.service"
+  }
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("load_config_intervals_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let config = UserConfig::from_path(&filepath).await.unwrap();
+        assert_eq!(config.metrics.interval, Duration::from_secs(30));
+        assert_eq!(
+            config.metrics.cpu_seconds.interval,
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(config.metrics.systemd_unit_state.interval, None);
+    });
+}
+
+#[test]
+fn load_config_server_and_log() {
+    let configstr = r#"
+server {
+  listen "0.0.0.0" "[::1]:9101"
+  port 9100
+  grace_period_ms 2000
+  allow_ips "10.0.0.0/8" "192.168.1.10"
+  deny_ips "10.0.0.1"
+  socket {
+    mode "0660"
+    owner "litemon"
+    group "prometheus"
+  }
+  tls {
+    cert "/etc/litemon/cert.pem"
+    key "/etc/litemon/key.pem"
+    client_ca "/etc/litemon/ca.pem"
+    allowed_names "prometheus.example.com"
+  }
+  auth {
+    basic_auth_users {
+      prometheus "$2b$08$185R6dM3crOTOoJnXsOHAevm3aJTF1x2dt6ADAh2/ghm.n.KlMmRu"
+    }
+    bearer_token_file "/etc/litemon/tokens"
+  }
+}
+log {
+  level "info"
+  format "json"
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("load_config_server_and_log_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let mut config = UserConfig::from_path(&filepath).await.unwrap();
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(config.server.grace_period, Duration::from_secs(2));
+        assert_eq!(
+            config.server.listen_addrs().unwrap(),
+            [
+                ListenAddr::Tcp("0.0.0.0:9100".parse().unwrap()),
+                ListenAddr::Tcp("[::1]:9101".parse().unwrap()),
+            ]
+        );
+        let allow_ips = config.server.allow_ips.iter().map(ToString::to_string);
+        assert!(allow_ips.eq(["10.0.0.0/8", "192.168.1.10/32"]));
+        assert_eq!(config.server.deny_ips[0].to_string(), "10.0.0.1/32");
+        assert_eq!(config.server.socket.mode, Some(0o660));
+        assert_eq!(config.server.socket.owner.as_deref(), Some("litemon"));
+        assert_eq!(config.server.socket.group.as_deref(), Some("prometheus"));
+        let tls = config.server.tls.as_ref().unwrap();
+        assert_eq!(tls.cert, PathBuf::from("/etc/litemon/cert.pem"));
+        assert_eq!(tls.key, PathBuf::from("/etc/litemon/key.pem"));
+        assert_eq!(tls.client_ca, Some(PathBuf::from("/etc/litemon/ca.pem")));
+        assert_eq!(tls.allowed_names, ["prometheus.example.com"]);
+        let auth = config.server.auth.as_ref().unwrap();
+        assert_eq!(auth.basic_auth_users.len(), 1);
+        assert_eq!(auth.basic_auth_users[0].0, "prometheus");
+        assert_eq!(
+            auth.bearer_token_file,
+            Some(PathBuf::from("/etc/litemon/tokens"))
+        );
+        assert_eq!(config.log.level.as_deref(), Some("info"));
+        assert_eq!(config.log.format, LogFormat::Json);
+
+        // Password hashes are not printed.
+        let printed = config.to_string();
+        assert!(printed.contains("prometheus <redacted>"));
+        assert!(!printed.contains("$2b$"));
+
+        let args = CliArgs::from_args(["litemon", "--port", "1234"]).unwrap();
+        config.apply_args(&args);
+        assert_eq!(config.server.listen, ["0.0.0.0", "[::1]:9101"]);
+        assert_eq!(config.server.port, 1234);
+
+        let unix_args =
+            CliArgs::from_args(["litemon", "--listen", "unix:/run/litemon.sock"]).unwrap();
+        config.apply_args(&unix_args);
+        assert_eq!(
+            config.server.listen_addrs().unwrap(),
+            [ListenAddr::Unix(PathBuf::from("/run/litemon.sock"))]
+        );
+    });
+}
+
+#[test]
+fn reject_invalid_config() {
+    let configstr = r#"
+metrics {
+  cpu_seconds enable=#true
+  loadavg enabled="yes"
+  memory_used enabled=#true {
+    fields "Dirty" "Bogus"
+  }
+  network_throughput enabled=#true {
+    interfaces "eth0" 42
+  }
+  unknown_metric enabled=#true
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_invalid_config_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        let locations = err
+            .errors
+            .iter()
+            .map(|el| (el.line, el.column))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [(11, 3), (3, 15), (4, 11), (6, 20), (9, 23)]);
+        assert!(err.errors[1].message.contains("unknown property `enable`"));
+        assert!(err.errors[2].message.contains("must be #true or #false"));
+        assert!(err.errors[3].message.contains("Bogus"));
+        assert!(err.errors[4].message.contains("must be strings"));
+    });
+}
+
+#[test]
+fn reject_duplicate_memory_fields() {
+    let configstr = r#"
+metrics {
+  memory_used enabled=#true {
+    fields "Dirty" "Shmem" "Dirty"
+  }
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_duplicate_memory_fields_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!((err.errors[0].line, err.errors[0].column), (4, 28));
+        assert!(
+            err.errors[0]
+                .message
+                .contains("duplicate field `Dirty` in `fields`")
+        );
+    });
+}
+
+#[test]
+fn reject_zero_timeout() {
+    let configstr = r"
+metrics timeout_ms=0 {
+  loadavg enabled=#true timeout_ms=0
+}
+        ";
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_zero_timeout_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        let locations = err
+            .errors
+            .iter()
+            .map(|el| (el.line, el.column))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [(2, 9), (3, 25)]);
+        assert!(
+            err.errors
+                .iter()
+                .all(|el| el.message.contains("`timeout_ms` must be greater than 0"))
+        );
+    });
+}
+
+#[test]
+fn reject_invalid_intervals() {
+    let configstr = r"
+metrics timeout_ms=2000 {
+  cpu_seconds enabled=#true interval_ms=0
+  loadavg enabled=#true interval_ms=1000
+  pressure enabled=#true timeout_ms=20000
+}
+        ";
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_invalid_intervals_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        let locations = err
+            .errors
+            .iter()
+            .map(|el| (el.line, el.column))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [(3, 29), (4, 3), (5, 3)]);
+        assert!(
+            err.errors[0]
+                .message
+                .contains("`interval_ms` must be greater than 0")
+        );
+        assert!(err.errors[1].message.contains("must be less than"));
+        assert!(err.errors[2].message.contains("must be less than"));
+    });
+}
+
+#[test]
+fn cpu_seconds_period() {
+    let configstr = r"
+metrics {
+  cpu_seconds enabled=#true
+}
+        ";
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("cpu_seconds_period_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async {
+        let config = UserConfig::from_path(&filepath).await.unwrap();
+        assert_eq!(
+            config.metrics.cpu_seconds.period,
+            Duration::from_millis(200)
+        );
+    });
+
+    let invalid = r"
+metrics {
+  cpu_seconds enabled=#true period_ms=1000 timeout_ms=1000
+}
+        ";
+    std::fs::write(&filepath, invalid).unwrap();
+
+    smol::block_on(async {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!((err.errors[0].line, err.errors[0].column), (3, 3));
+        assert!(
+            err.errors[0]
+                .message
+                .contains("`period_ms` (1000) of `cpu_seconds` must be less than")
+        );
+    });
+
+    // The default period does not fit into the timeout, without a `cpu_seconds` node.
+    let short_timeout = r"
+metrics timeout_ms=100 interval_ms=1000
+        ";
+    std::fs::write(&filepath, short_timeout).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!((err.errors[0].line, err.errors[0].column), (2, 1));
+        assert!(err.errors[0].message.contains(
+            "`period_ms` (200) of `cpu_seconds` must be less than its `timeout_ms` (100)"
+        ));
+    });
+}
+
+#[test]
+fn reject_invalid_vmstat_fields() {
+    let configstr = r#"
+metrics {
+  vmstat enabled=#true {
+    fields "pgfault" "oom-kill" "pgmajfault{x=\"y\"}" ""
+  }
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_invalid_vmstat_fields_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        let locations = err
+            .errors
+            .iter()
+            .map(|el| (el.line, el.column))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [(4, 22), (4, 33), (4, 55)]);
+        assert!(
+            err.errors[0]
+                .message
+                .contains("invalid vmstat field `oom-kill`")
+        );
+    });
+
+    let duplicate = r#"
+metrics {
+  vmstat enabled=#true {
+    fields "pgfault" "nr_dirty" "pgfault"
+  }
+}
+        "#;
+    std::fs::write(&filepath, duplicate).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!((err.errors[0].line, err.errors[0].column), (4, 33));
+        assert!(
+            err.errors[0]
+                .message
+                .contains("duplicate field `pgfault` in `fields`")
+        );
+    });
+}
+
+#[test]
+fn reject_empty_auth() {
+    let configstr = r"
+server {
+  auth {
+    basic_auth_users {
+    }
+  }
+}
+        ";
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("reject_empty_auth_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    smol::block_on(async move {
+        let err = UserConfig::from_path(&filepath).await.unwrap_err();
+        let err = err.downcast::<ConfigErrors>().unwrap();
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!((err.errors[0].line, err.errors[0].column), (3, 3));
+        assert!(
+            err.errors[0]
+                .message
+                .contains("`auth` requires users in `basic_auth_users`")
+        );
     });
 }
@@ -0,0 +1,304 @@
+//! Tests for the format module.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use http::{HeaderMap, HeaderValue, header};
+use litemon::collector::Collector;
+use litemon::config::UserConfig;
+use litemon::format::Format;
+use litemon::protobuf::{self, MetricFamily, MetricType};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::Histogram;
+use prometheus_client::registry::Registry;
+use prost::Message;
+
+#[test]
+fn negotiate_format() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(Format::negotiate(&headers), Format::PrometheusText);
+
+    headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+    assert_eq!(Format::negotiate(&headers), Format::PrometheusText);
+
+    // Sent by Prometheus.
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static(
+            "application/openmetrics-text;version=1.0.0;q=0.5,text/plain;version=0.0.4;q=0.2,*/*;q=0.1",
+        ),
+    );
+    assert_eq!(Format::negotiate(&headers), Format::OpenMetrics);
+
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static("application/openmetrics-text;q=0.5,text/plain;q=0.9"),
+    );
+    assert_eq!(Format::negotiate(&headers), Format::PrometheusText);
+
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static(
+            "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,text/plain;version=0.0.4;q=0.3",
+        ),
+    );
+    assert_eq!(Format::negotiate(&headers), Format::Protobuf);
+}
+
+/// Histogram with a single bucket at 0.5, besides `+Inf`.
+fn histogram() -> Histogram {
+    Histogram::new([0.5_f64])
+}
+
+#[test]
+#[allow(clippy::float_cmp)]
+fn convert_to_protobuf() {
+    let mut registry = Registry::default();
+    let histogram =
+        Family::<Vec<(String, String)>, Histogram, fn() -> Histogram>::new_with_constructor(
+            histogram,
+        );
+    registry.register(
+        "litemon_scrape_seconds",
+        "Scrape duration",
+        histogram.clone(),
+    );
+    let cpu = histogram.get_or_create(&vec![("collector".to_owned(), "cpu".to_owned())]);
+    for value in [0.125_f64, 0.125_f64, 1.0_f64] {
+        cpu.observe(value);
+    }
+    let counter = Counter::<u64>::default();
+    registry.register(
+        "litemon_vmstat_pgfault",
+        "Number of page faults",
+        counter.clone(),
+    );
+    counter.inc_by(42);
+
+    let families = protobuf::from_registry(&registry).unwrap();
+    let encoded = Format::Protobuf.encode_families(&families).unwrap();
+    let mut buf = encoded.as_slice();
+    let histogram_family = MetricFamily::decode_length_delimited(&mut buf).unwrap();
+    let counter_family = MetricFamily::decode_length_delimited(&mut buf).unwrap();
+    assert!(buf.is_empty());
+
+    assert_eq!(histogram_family.name(), "litemon_scrape_seconds");
+    assert_eq!(histogram_family.help(), "Scrape duration.");
+    assert_eq!(histogram_family.r#type(), MetricType::Histogram);
+    assert_eq!(histogram_family.metric.len(), 1);
+    assert_eq!(histogram_family.metric[0].label.len(), 1);
+    let decoded = histogram_family.metric[0].histogram.as_ref().unwrap();
+    assert_eq!(decoded.bucket[0].upper_bound(), 0.5_f64);
+    assert_eq!(decoded.bucket[0].cumulative_count(), 2);
+    assert_eq!(decoded.bucket[1].upper_bound(), f64::INFINITY);
+    assert_eq!(decoded.bucket[1].cumulative_count(), 3);
+    assert_eq!(decoded.sample_count(), 3);
+    assert_eq!(decoded.sample_sum(), 1.25_f64);
+
+    assert_eq!(counter_family.name(), "litemon_vmstat_pgfault_total");
+    assert_eq!(counter_family.r#type(), MetricType::Counter);
+    assert_eq!(
+        counter_family.metric[0].counter.as_ref().unwrap().value(),
+        42.0_f64
+    );
+
+    let err = Format::OpenMetrics.encode_families(&families).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("is converted from OpenMetrics text")
+    );
+}
+
+#[test]
+fn convert_to_prometheus_text() {
+    let openmetrics = r#"# HELP litemon_cpu_seconds Seconds spent in "each" mode.
+# TYPE litemon_cpu_seconds counter
+litemon_cpu_seconds_total{mode="user # not an exemplar"} 1.5 # {trace_id="abc"} 1.0
+litemon_cpu_seconds_created{mode="user # not an exemplar"} 1700000000.0
+# HELP litemon_node_uptime Uptime in seconds.
+# TYPE litemon_node_uptime unknown
+litemon_node_uptime 42
+# EOF
+"#;
+    let expected = r#"# HELP litemon_cpu_seconds_total Seconds spent in "each" mode.
+# TYPE litemon_cpu_seconds_total counter
+litemon_cpu_seconds_total{mode="user # not an exemplar"} 1.5
+# HELP litemon_node_uptime Uptime in seconds.
+# TYPE litemon_node_uptime untyped
+litemon_node_uptime 42
+"#;
+
+    let converted = Format::PrometheusText
+        .encode(openmetrics.to_owned())
+        .unwrap();
+    assert_eq!(String::from_utf8(converted).unwrap(), expected);
+}
+
+#[test]
+fn convert_to_json() {
+    let mut registry = Registry::default();
+    let histogram = histogram();
+    registry.register(
+        "litemon_scrape_seconds",
+        "Scrape duration",
+        histogram.clone(),
+    );
+    for value in [0.125_f64, 0.125_f64, 1.0_f64] {
+        histogram.observe(value);
+    }
+    let info = Family::<Vec<(String, String)>, Gauge>::default();
+    registry.register("litemon_node_info", "System information", info.clone());
+    info.get_or_create(&vec![("hostname".to_owned(), "host".to_owned())])
+        .set(1);
+    // Without samples, e.g., for an interface that does not exist.
+    let received = Family::<Vec<(String, String)>, Counter>::default();
+    registry.register("litemon_network_receive_bytes", "Received bytes", received);
+
+    let families = protobuf::from_registry(&registry).unwrap();
+    let encoded = Format::Json.encode_families(&families).unwrap();
+    let json = serde_json::from_slice::<serde_json::Value>(&encoded).unwrap();
+    let expected = serde_json::json!([
+        {
+            "name": "litemon_scrape_seconds",
+            "type": "histogram",
+            "help": "Scrape duration.",
+            "metrics": [
+                { "labels": {}, "buckets": { "0.5": 2_u64, "+Inf": 3_u64 }, "count": 3_u64, "sum": 1.25_f64 }
+            ]
+        },
+        {
+            "name": "litemon_node_info",
+            "type": "gauge",
+            "help": "System information.",
+            "metrics": [{ "labels": { "hostname": "host" }, "value": 1.0_f64 }]
+        }
+    ]);
+    assert_eq!(json, expected);
+}
+
+/// Families of `openmetrics` with their protobuf name, type and values of the samples, except
+/// `_created`.
+fn openmetrics_families(openmetrics: &str) -> Vec<(String, MetricType, Vec<f64>)> {
+    let mut ret: Vec<(String, MetricType, Vec<f64>)> = Vec::new();
+    for line in openmetrics.lines() {
+        if let Some(declaration) = line.strip_prefix("# TYPE ") {
+            let (name, om_type) = declaration.split_once(' ').expect("TYPE line");
+            let (name, metric_type) = match om_type {
+                "counter" => (format!("{name}_total"), MetricType::Counter),
+                "gauge" => (name.to_owned(), MetricType::Gauge),
+                "info" => (format!("{name}_info"), MetricType::Gauge),
+                "histogram" => (name.to_owned(), MetricType::Histogram),
+                _ => (name.to_owned(), MetricType::Untyped),
+            };
+            ret.push((name, metric_type, Vec::new()));
+        } else if !line.starts_with('#') && !line.is_empty() {
+            let (name, value) = line.rsplit_once(' ').expect("sample line");
+            if name
+                .split('{')
+                .next()
+                .is_some_and(|el| el.ends_with("_created"))
+            {
+                continue;
+            }
+            let (_, _, values) = ret.last_mut().expect("sample outside of a family");
+            values.push(value.parse().expect("sample value"));
+        }
+    }
+
+    ret
+}
+
+/// Values of the samples in `family`, in the order of the OpenMetrics text.
+fn protobuf_values(family: &MetricFamily) -> Vec<f64> {
+    let mut ret = Vec::new();
+    for metric in &family.metric {
+        match family.r#type() {
+            MetricType::Counter => ret.push(metric.counter.as_ref().expect("counter").value()),
+            MetricType::Gauge => ret.push(metric.gauge.as_ref().expect("gauge").value()),
+            MetricType::Untyped => ret.push(metric.untyped.as_ref().expect("untyped").value()),
+            MetricType::Histogram => {
+                let histogram = metric.histogram.as_ref().expect("histogram");
+                #[allow(clippy::cast_precision_loss)]
+                ret.extend(
+                    histogram
+                        .bucket
+                        .iter()
+                        .map(|el| el.cumulative_count() as f64),
+                );
+                ret.push(histogram.sample_sum());
+                #[allow(clippy::cast_precision_loss)]
+                ret.push(histogram.sample_count() as f64);
+            }
+            MetricType::Summary => unreachable!("litemon has no summaries"),
+        }
+    }
+
+    ret
+}
+
+#[test]
+fn protobuf_roundtrip_collectors() {
+    // Everything but `systemd_unit_state`, which requires the system D-Bus.
+    let configstr = r#"
+metrics {
+  cpu_seconds enabled=#true period_ms=50
+  loadavg enabled=#true
+  memory_used enabled=#true {
+    fields "Dirty" "HugePages_Total"
+  }
+  network_throughput enabled=#true
+  disk_usage enabled=#true {
+    mountpoints "/"
+  }
+  pressure enabled=#true
+  disk_stats enabled=#true {
+    mountpoints "/"
+  }
+  vmstat enabled=#true
+}
+        "#;
+    let tmp =
+        std::env::var("CARGO_TARGET_TMPDIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    let filepath = tmp.join("protobuf_roundtrip_collectors_test.kdl");
+    std::fs::write(&filepath, configstr).unwrap();
+
+    let (openmetrics, families) = smol::block_on(async move {
+        let config = UserConfig::from_path(&filepath).await.unwrap();
+        let collector = Collector::new();
+        collector.create_from_config(&config).await.unwrap();
+        collector.register().await.unwrap();
+        collector.start().await;
+        let started = Instant::now();
+        while !collector.is_ready().await {
+            assert!(started.elapsed() < Duration::from_secs(10), "not ready");
+            smol::Timer::after(Duration::from_millis(20)).await;
+        }
+        // Stop sampling, so both encode the same values.
+        collector.shutdown().await;
+        let openmetrics = collector.encode(&[]).await.unwrap();
+        let families = collector.families(&[]).await.unwrap();
+        (openmetrics, families)
+    });
+
+    let expected = openmetrics_families(&openmetrics);
+    let encoded = Format::Protobuf.encode_families(&families).unwrap();
+    let mut buf = encoded.as_slice();
+    let mut decoded = Vec::new();
+    while !buf.is_empty() {
+        decoded.push(MetricFamily::decode_length_delimited(&mut buf).unwrap());
+    }
+    assert_eq!(decoded, families);
+
+    assert_eq!(families.len(), expected.len(), "{openmetrics}");
+    for (family, (name, metric_type, values)) in families.iter().zip(&expected) {
+        assert_eq!(family.name(), name);
+        assert_eq!(family.r#type(), *metric_type, "{name}");
+        assert!(family.help.is_some(), "{name}");
+        assert_eq!(protobuf_values(family), *values, "{name}");
+    }
+    for name in ["litemon_cpu_seconds_total", "litemon_vmstat_pgfault_total"] {
+        assert!(families.iter().any(|el| el.name() == name), "{name}");
+    }
+}
@@ -0,0 +1,22 @@
+//! Tests for the http module.
+
+use litemon::config::ServerConfig;
+
+#[test]
+fn failed_bind_removes_bound_sockets() {
+    let dir = std::env::temp_dir().join(format!("litemon-bind-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let bound = dir.join("first.sock");
+    let server = ServerConfig {
+        listen: vec![
+            format!("unix:{}", bound.display()),
+            format!("unix:{}", dir.join("missing/second.sock").display()),
+        ],
+        ..ServerConfig::default()
+    };
+
+    let result = smol::block_on(litemon::http::bind(&server));
+    assert!(result.is_err());
+    assert!(!bound.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,39 @@
+//! Tests for the http_utils module.
+
+use litemon::http_utils::query_pairs;
+
+fn owned(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    expected
+        .iter()
+        .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+        .collect()
+}
+
+#[test]
+fn decode_query() {
+    assert_eq!(
+        query_pairs("collect[]=cpu&collect%5B%5D=vmstat&collect%5b%5d=disk%5Fusage"),
+        owned(&[
+            ("collect[]", "cpu"),
+            ("collect[]", "vmstat"),
+            ("collect[]", "disk_usage")
+        ])
+    );
+    assert_eq!(
+        query_pairs("a=b+c%20d&flag&e="),
+        owned(&[("a", "b c d"), ("e", "")])
+    );
+    // Invalid escapes are kept literally, signs are no hex digits.
+    assert_eq!(
+        query_pairs("a=100%&b=%zz&c=%4&d=%+1&e=%-1"),
+        owned(&[
+            ("a", "100%"),
+            ("b", "%zz"),
+            ("c", "%4"),
+            ("d", "% 1"),
+            ("e", "%-1")
+        ])
+    );
+    assert_eq!(query_pairs("a=%C3%A4"), owned(&[("a", "\u{e4}")]));
+    assert!(query_pairs("").is_empty());
+}
@@ -1,4 +1,12 @@
 //! Tests for litemon.
 
+mod acl;
+mod auth;
 mod cliargs;
+mod collector;
+mod compression;
 mod config;
+mod format;
+mod http;
+mod http_utils;
+mod tls;
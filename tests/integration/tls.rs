@@ -0,0 +1,63 @@
+//! Tests for the tls module.
+
+use futures_rustls::rustls::pki_types::CertificateDer;
+use litemon::tls::check_allowed_names;
+use rcgen::{CertificateParams, DnType, KeyPair, SanType};
+
+/// Self-signed certificate with subject `common_name` and the DNS names or IP addresses in
+/// `alt_names`.
+fn certificate(common_name: &str, alt_names: &[&str]) -> CertificateDer<'static> {
+    let alt_names = alt_names
+        .iter()
+        .map(|el| (*el).to_owned())
+        .collect::<Vec<_>>();
+    let mut params = CertificateParams::new(alt_names).expect("certificate parameters");
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+    params.subject_alt_names.push(SanType::Rfc822Name(
+        "ops@example.com".try_into().expect("email address"),
+    ));
+    let key = KeyPair::generate().expect("key pair");
+
+    params.self_signed(&key).expect("certificate").der().clone()
+}
+
+#[test]
+fn allowed_names_match_cn_and_san() {
+    let cert = certificate(
+        "prometheus",
+        &["scraper.example.com", "192.0.2.10", "2001:db8::1"],
+    );
+    let allowed = |name: &str| check_allowed_names(&cert, &[name.to_owned()]);
+
+    allowed("prometheus").unwrap();
+    allowed("scraper.example.com").unwrap();
+    allowed("192.0.2.10").unwrap();
+    allowed("2001:db8::1").unwrap();
+    allowed("ops@example.com").unwrap();
+
+    check_allowed_names(
+        &cert,
+        &["other".to_owned(), "scraper.example.com".to_owned()],
+    )
+    .unwrap();
+}
+
+#[test]
+fn allowed_names_reject_others() {
+    let cert = certificate("prometheus", &["scraper.example.com"]);
+    let denied = |name: &str| check_allowed_names(&cert, &[name.to_owned()]).unwrap_err();
+
+    let err = denied("grafana");
+    assert!(
+        err.to_string()
+            .contains("prometheus, scraper.example.com, ops@example.com"),
+        "{err}"
+    );
+    // Names are matched exactly, without wildcards or case folding.
+    denied("Prometheus");
+    denied("*.example.com");
+    denied("example.com");
+    denied("");
+}